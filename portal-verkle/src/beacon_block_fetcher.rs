@@ -1,11 +1,13 @@
 use std::{
     fs::File,
     io::{BufReader, BufWriter},
+    ops::Range,
 };
 
 use anyhow::bail;
+use futures::{stream, Stream, StreamExt};
 use reqwest::{Client, Url};
-use serde::Deserialize;
+use serde_json::json;
 
 use crate::{
     types::{beacon::SignedBeaconBlock, JsonResponseMessage},
@@ -15,7 +17,8 @@ use crate::{
 const BEACON_BLOCK_URL_PATH: &str = "eth/v2/beacon/blocks/";
 
 pub struct BeaconBlockFetcher {
-    rpc_url: String,
+    /// If not present, beacon blocks are only read from local files.
+    rpc_url: Option<String>,
     save_locally: bool,
     client: Client,
 }
@@ -23,31 +26,59 @@ pub struct BeaconBlockFetcher {
 impl BeaconBlockFetcher {
     pub fn new(rpc_url: &str, save_locally: bool) -> Self {
         Self {
-            rpc_url: rpc_url.to_string(),
+            rpc_url: Some(rpc_url.to_string()),
             save_locally,
             client: Client::new(),
         }
     }
 
+    /// Creates the fetcher that only reads beacon blocks from local files (see
+    /// [beacon_slot_path]), without using RPC.
+    ///
+    /// Slots whose files are missing are considered empty.
+    pub fn from_files() -> Self {
+        Self {
+            rpc_url: None,
+            save_locally: false,
+            client: Client::new(),
+        }
+    }
+
+    /// Fetches beacon blocks for the given slots, up to `concurrency` of them at the same time.
+    ///
+    /// The blocks are yielded in the order of the slots. Missing slots are yielded as `None`.
+    pub fn fetch_block_range(
+        &self,
+        slots: Range<u64>,
+        concurrency: usize,
+    ) -> impl Stream<Item = (u64, anyhow::Result<Option<SignedBeaconBlock>>)> + '_ {
+        stream::iter(slots)
+            .map(|slot| async move { (slot, self.fetch_beacon_block(slot).await) })
+            .buffered(concurrency.max(1))
+    }
+
     pub async fn fetch_beacon_block(&self, slot: u64) -> anyhow::Result<Option<SignedBeaconBlock>> {
         let path = beacon_slot_path(slot);
         if path.exists() {
             let reader = BufReader::new(File::open(path)?);
-            let message: JsonResponseMessage = serde_json::from_reader(reader)?;
-            match message {
+            let response: serde_json::Value = serde_json::from_reader(reader)?;
+            match JsonResponseMessage::parse(&response)? {
                 JsonResponseMessage::Success(success_message) => Ok(Some(success_message.data)),
+                JsonResponseMessage::BareBlock(beacon_block) => Ok(Some(beacon_block)),
                 JsonResponseMessage::Error(error_message) => {
                     bail!("Error reading beacon slot file {slot}: {:?}", error_message)
                 }
             }
         } else {
-            let url = Url::parse(&self.rpc_url)?
+            let Some(rpc_url) = &self.rpc_url else {
+                return Ok(None);
+            };
+            let url = Url::parse(rpc_url)?
                 .join(BEACON_BLOCK_URL_PATH)?
                 .join(&slot.to_string())?;
             let response = self.client.get(url).send().await?;
             let response: serde_json::Value = response.json().await?;
-            let message = JsonResponseMessage::deserialize(&response)?;
-            match message {
+            match JsonResponseMessage::parse(&response)? {
                 JsonResponseMessage::Success(success_message) => {
                     if self.save_locally {
                         let writer = BufWriter::new(File::create(path)?);
@@ -55,6 +86,14 @@ impl BeaconBlockFetcher {
                     }
                     Ok(Some(success_message.data))
                 }
+                JsonResponseMessage::BareBlock(beacon_block) => {
+                    // Saved in the same format as other responses
+                    if self.save_locally {
+                        let writer = BufWriter::new(File::create(path)?);
+                        serde_json::to_writer_pretty(writer, &json!({ "data": response }))?;
+                    }
+                    Ok(Some(beacon_block))
+                }
                 JsonResponseMessage::Error(error_message) => {
                     if error_message.code == 404 {
                         Ok(None)
@@ -1,7 +1,12 @@
-use anyhow::bail;
-use clap::Parser;
+use std::time::Instant;
+
+use alloy_primitives::hex;
+use anyhow::{anyhow, bail};
+use clap::{ArgAction, Parser};
+use ethportal_api::{ContentValue, OverlayContentKey, VerkleContentKey};
 use portal_verkle::{
-    beacon_block_fetcher::BeaconBlockFetcher, state_trie_fetcher::StateTrieFetcher,
+    beacon_block_fetcher::BeaconBlockFetcher, content_validation::ValidateContent,
+    portal_client::PortalClientArgs, state_trie_fetcher::StateTrieFetcher,
 };
 use portal_verkle_primitives::verkle::genesis_config::GenesisConfig;
 
@@ -17,11 +22,30 @@ pub struct Args {
     pub beacon_rpc_url: String,
     #[arg(long, default_value_t = String::from(LOCALHOST_PORTAL_RPC_URL))]
     pub portal_rpc_url: String,
+    #[command(flatten)]
+    pub portal_client: PortalClientArgs,
+    /// Whether to verify the fetched content against the commitments from their content keys.
+    ///
+    /// Should be disabled only when fetching from a trusted Portal Network node. The root of the
+    /// fetched state trie is checked either way.
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub verify_proofs: bool,
+    /// Fetch and print the content with the given hex encoded content key (e.g. from a log).
+    #[arg(long, value_parser = parse_content_key)]
+    pub content_key: Option<VerkleContentKey>,
+}
+
+/// Parses the hex encoded content key (the inverse of [OverlayContentKey::to_hex]).
+fn parse_content_key(content_key: &str) -> anyhow::Result<VerkleContentKey> {
+    let bytes = hex::decode(content_key)?;
+    VerkleContentKey::try_from(bytes)
+        .map_err(|err| anyhow!("Invalid content key {content_key}: {err:?}"))
 }
 
 struct StateVerifier {
     block_fetcher: BeaconBlockFetcher,
     state_trie_fetcher: StateTrieFetcher,
+    verify_proofs: bool,
 }
 
 impl StateVerifier {
@@ -29,10 +53,12 @@ impl StateVerifier {
         println!("Initializing...");
         let block_fetcher =
             BeaconBlockFetcher::new(&args.beacon_rpc_url, /* save_locally = */ false);
-        let state_trie_fetcher = StateTrieFetcher::new(&args.portal_rpc_url)?;
+        let state_trie_fetcher =
+            StateTrieFetcher::new(&args.portal_rpc_url, args.portal_client.config())?;
         Ok(Self {
             block_fetcher,
             state_trie_fetcher,
+            verify_proofs: args.verify_proofs,
         })
     }
 
@@ -46,23 +72,34 @@ impl StateVerifier {
             beacon_block.message.body.execution_payload.state_root
         };
         println!("Veryfing slot {slot} with state root: {root}");
-        match self.state_trie_fetcher.fetch_state_trie(root).await {
-            Ok(synced_state_trie) => {
-                if synced_state_trie.root() == root {
-                    println!("SUCCESS");
-                } else {
-                    println!(
-                        "ERROR: State trie fetched but root is different! Expected {root} but received {}",
-                        synced_state_trie.root()
-                    )
-                }
-            }
+        let timer = Instant::now();
+        let result = if self.verify_proofs {
+            self.state_trie_fetcher.fetch_state_trie(root).await
+        } else {
+            self.state_trie_fetcher
+                .fetch_state_trie_unchecked(root)
+                .await
+        };
+        match result {
+            Ok(_) => println!("SUCCESS (elapsed: {:?})", timer.elapsed()),
             Err(err) => {
                 println!("ERROR: Error while fetching state trie: {err}")
             }
         };
         Ok(())
     }
+
+    async fn print_content(&self, key: &VerkleContentKey) -> anyhow::Result<()> {
+        println!("Fetching content with key: {}", key.to_hex());
+        let value = self.state_trie_fetcher.fetch_content(key).await?;
+        if self.verify_proofs {
+            value.validate_against_key(key)?;
+        } else {
+            value.validate_structure_against_key(key)?;
+        }
+        println!("Content value: {}", value.to_hex());
+        Ok(())
+    }
 }
 
 #[tokio::main]
@@ -70,8 +107,43 @@ async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     let verifier = StateVerifier::new(&args)?;
+    if let Some(content_key) = &args.content_key {
+        verifier.print_content(content_key).await?;
+    }
     for slot in args.slots {
         verifier.verify_state(slot).await?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Address;
+    use ethportal_api::types::content_key::verkle::LeafFragmentKey;
+    use portal_verkle_primitives::{verkle::storage::AccountStorageLayout, Point};
+
+    use super::*;
+
+    #[test]
+    fn parse_content_key_round_trip() -> anyhow::Result<()> {
+        let commitment = Point::from(&GenesisConfig::DEVNET6_STATE_ROOT);
+        let content_keys = [
+            VerkleContentKey::Bundle(commitment.clone()),
+            VerkleContentKey::BranchFragment(commitment.clone()),
+            VerkleContentKey::LeafFragment(LeafFragmentKey {
+                stem: *AccountStorageLayout::new(Address::ZERO).account_storage_stem(),
+                commitment,
+            }),
+        ];
+        for content_key in content_keys {
+            assert_eq!(parse_content_key(&content_key.to_hex())?, content_key);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid_content_key() {
+        assert!(parse_content_key("0x").is_err());
+        assert!(parse_content_key("not hex").is_err());
+    }
+}
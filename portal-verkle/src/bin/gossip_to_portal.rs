@@ -1,20 +1,41 @@
 use std::{
     cmp::Ordering,
-    collections::{BTreeMap, HashSet},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    pin::pin,
+    process,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
-use alloy_primitives::B256;
-use clap::Parser;
+use alloy_primitives::{keccak256, Address, B256};
+use anyhow::{bail, Context};
+use clap::{Parser, ValueEnum};
 use ethportal_api::{
-    types::content_key::verkle::LeafFragmentKey, VerkleContentKey, VerkleContentValue,
-    VerkleNetworkApiClient,
+    types::content_key::verkle::LeafFragmentKey, ContentValue, OverlayContentKey, VerkleContentKey,
+    VerkleContentValue,
 };
-use futures::future;
+use futures::{future, StreamExt};
 use itertools::{zip_eq, Itertools};
-use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
 use portal_verkle::{
-    beacon_block_fetcher::BeaconBlockFetcher, evm::VerkleEvm, utils::read_genesis,
+    beacon_block_fetcher::BeaconBlockFetcher,
+    content_value::strip_proof,
+    evm::{ProcessBlockResult, VerkleEvm},
+    portal_client::{PortalClientArgs, ResilientPortalClient},
+    portal_transport::PortalTransport,
+    state_trie_fetcher::StateTrieFetcher,
+    trie_snapshot::{TrieSnapshot, TrieSnapshotBuilder},
+    types::{
+        beacon::{ExecutionPayload, SignedBeaconBlock},
+        witness::ExecutionWitness,
+    },
+    utils::{genesis_path, read_genesis_from_file},
 };
 use portal_verkle_primitives::{
     constants::PORTAL_NETWORK_NODE_WIDTH,
@@ -26,33 +47,427 @@ use portal_verkle_primitives::{
             portal_branch_node_builder::PortalBranchNodeBuilder,
             portal_leaf_node_builder::PortalLeafNodeBuilder,
         },
-        StateWrites,
+        storage::AccountStorageLayout,
+        StateWrites, VerkleTrie,
     },
     Stem,
 };
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Serialize;
+use tokio::{signal, sync::mpsc};
 
 const LOCALHOST_BEACON_RPC_URL: &str = "http://localhost:9596/";
 const LOCALHOST_PORTAL_RPC_URL: &str = "http://localhost:8545/";
 
+/// The maximum number of slots to gossip when looking for `--until-block-hash`, if `--slots` is
+/// not set.
+const MAX_SLOTS_UNTIL_BLOCK_HASH: u64 = 1_000_000;
+
+const SLOTS_PER_EPOCH: u64 = 32;
+
+/// Where beacon blocks are read from.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeaconSource {
+    /// Beacon RPC (`--beacon-rpc-url`), unless the block is saved locally.
+    Rpc,
+    /// Only locally saved files. Slots without files are considered empty.
+    Files,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    /// The number of slots to gossip, or the maximum number of slots if `--until-block-hash` is
+    /// set. Slots are counted from genesis, or from the slot of the `--input-trie`.
+    #[arg(long, required_unless_present_any = ["until_block_hash", "epoch", "witness_file"])]
+    pub slots: Option<u64>,
+    /// Gossip slots until the block with the given hash is gossiped.
     #[arg(long)]
-    pub slots: u64,
+    pub until_block_hash: Option<B256>,
+    /// Gossip all slots of the given epoch, instead of `--slots`.
+    ///
+    /// Slots before the epoch (starting from genesis or the `--input-trie`) are processed, but
+    /// not gossiped.
+    #[arg(long, conflicts_with_all = ["slots", "until_block_hash"])]
+    pub epoch: Option<u64>,
+    /// Gossip the state changes of the execution witness from the given json file, instead of
+    /// processing beacon blocks (e.g. for the witness obtained from another client).
+    ///
+    /// The state (genesis or the `--input-trie`) has to be the pre-state of the witness.
+    ///
+    /// Not supported yet: the proof of the witness has to be verified against the pre-state root
+    /// before its content is gossiped, which isn't implemented, so this always fails.
+    #[arg(
+        long,
+        requires = "witness_block_hash",
+        conflicts_with_all = ["slots", "until_block_hash", "epoch", "output_trie"]
+    )]
+    pub witness_file: Option<PathBuf>,
+    /// The hash of the block of the `--witness-file`.
+    #[arg(long, requires = "witness_file")]
+    pub witness_block_hash: Option<B256>,
+    #[arg(long, value_enum, default_value_t = BeaconSource::Rpc)]
+    pub beacon_source: BeaconSource,
     #[arg(long, default_value_t = String::from(LOCALHOST_BEACON_RPC_URL))]
     pub beacon_rpc_url: String,
     #[arg(long, default_value_t = String::from(LOCALHOST_PORTAL_RPC_URL))]
     pub portal_rpc_url: String,
+    #[command(flatten)]
+    pub portal_client: PortalClientArgs,
+    #[arg(long, default_value_os_t = genesis_path())]
+    pub genesis_file: PathBuf,
+    #[arg(long, default_value_t = GenesisConfig::DEVNET6_BLOCK_HASH)]
+    pub genesis_block_hash: B256,
+    /// After gossiping each slot, fetch a random sample of the gossiped content and report how
+    /// much of it is retrievable.
+    #[arg(long)]
+    pub verify_after_gossip: bool,
+    /// The number of gossiped content keys to fetch when `--verify-after-gossip` is set.
+    #[arg(long, default_value_t = 16)]
+    pub verify_sample_size: usize,
+    /// The fraction (between 0 and 1) of gossiped content keys to fetch when
+    /// `--verify-after-gossip` is set. If set, it's used instead of `--verify-sample-size`.
+    #[arg(long, value_parser = parse_sample_rate)]
+    pub verify_sample_rate: Option<f64>,
+    /// The seed of the random sampling of `--verify-after-gossip`, for reproducible runs.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// The file to which the keys of all gossiped content are appended, as newline-delimited
+    /// JSON.
+    ///
+    /// Every record also contains the hash of the block and its slot (except for the
+    /// `--witness-file`, whose slot is not known).
+    #[arg(long)]
+    pub log_gossiped_keys: Option<PathBuf>,
+    /// The number of beacon blocks that are fetched ahead of the slot that is being gossiped.
+    #[arg(long, default_value_t = 4)]
+    pub prefetch_depth: usize,
+    /// Compare the state with the execution witness before each block, in order to report
+    /// diverged stems if state root doesn't match.
+    #[arg(long)]
+    pub diagnose_state_root: bool,
+    /// The file to which the state trie is saved once gossiping is finished (see
+    /// `--input-trie`).
+    #[arg(long)]
+    pub output_trie: Option<PathBuf>,
+    /// Continue gossiping after the slot of the state trie saved with `--output-trie`, instead of
+    /// starting from genesis.
+    #[arg(long)]
+    pub input_trie: Option<PathBuf>,
+    /// The number of most recently gossiped content items that are remembered, so identical
+    /// content isn't gossiped again. Zero disables it.
+    ///
+    /// The proof is ignored when comparing the content. It commits to the block in which the
+    /// content is gossiped, while Portal Network nodes store the content without it, so the
+    /// content that only differs in the proof is already available.
+    #[arg(long, default_value_t = 100_000)]
+    pub gossip_cache_size: usize,
+    /// Gossip only the state of the given account.
+    ///
+    /// This covers only the account's header stem (the account header, the first 64 storage
+    /// slots and the first 128 code chunks), as other stems can't be matched to the address.
+    #[arg(long)]
+    pub filter_address: Option<Address>,
+    /// Print a json object for every slot, and the summary at the end, instead of the
+    /// human-readable output.
+    #[arg(long)]
+    pub json_output: bool,
+    /// The time of a slot, in seconds.
+    ///
+    /// A warning is logged whenever processing and gossiping the slots takes longer than that, as
+    /// the bridge is falling behind the chain.
+    #[arg(long, default_value_t = 12)]
+    pub slot_time_warning_secs: u64,
+    /// Continue with the next slot if gossiping a slot fails, and exit with error at the end.
+    ///
+    /// Only the gossiping errors are tolerated. If fetching or processing the block fails, the
+    /// state can't be updated for the following slots, so gossiping stops either way.
+    #[arg(long)]
+    pub continue_on_error: bool,
+}
+
+fn parse_sample_rate(sample_rate: &str) -> anyhow::Result<f64> {
+    let sample_rate: f64 = sample_rate.parse()?;
+    if !(0.0..=1.0).contains(&sample_rate) {
+        bail!("Sample rate has to be between 0 and 1, but is {sample_rate}")
+    }
+    Ok(sample_rate)
+}
+
+/// Prints the line, unless `--json-output` is set (the first argument).
+macro_rules! print_info {
+    ($json_output:expr, $($arg:tt)*) => {
+        if !$json_output {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// The content that was gossiped.
+struct GossipedContent {
+    key: VerkleContentKey,
+    value_size: usize,
+}
+
+/// The summary of the gossiped slots (of an epoch or the whole run).
+#[derive(Default)]
+struct GossipSummary {
+    blocks: usize,
+    empty_slots: usize,
+    gossiped: usize,
+    skipped: usize,
+}
+
+impl GossipSummary {
+    /// Adds the gossip report of a block, or `None` for an empty slot.
+    fn add(&mut self, report: Option<&GossipReport>) {
+        match report {
+            Some(report) => {
+                self.blocks += 1;
+                self.gossiped += report.gossiped.len();
+                self.skipped += report.skipped;
+            }
+            None => self.empty_slots += 1,
+        }
+    }
+
+    fn print_epoch(&self, epoch: u64) {
+        println!(
+            "Epoch {epoch}: {} blocks processed, {} empty slots skipped, {} nodes gossiped ({} \
+            skipped as already gossiped)",
+            self.blocks, self.empty_slots, self.gossiped, self.skipped
+        );
+    }
+}
+
+/// The result of gossiping content.
+#[derive(Default)]
+struct GossipReport {
+    gossiped: Vec<GossipedContent>,
+    /// The number of content items that weren't gossiped because identical content was already
+    /// gossiped.
+    skipped: usize,
+    elapsed: Duration,
+}
+
+impl GossipReport {
+    fn extend(&mut self, other: GossipReport) {
+        self.gossiped.extend(other.gossiped);
+        self.skipped += other.skipped;
+        self.elapsed += other.elapsed;
+    }
+}
+
+/// The result of gossiping the block of a slot.
+struct SlotReport {
+    block_number: u64,
+    block_hash: B256,
+    state_root: B256,
+    gossip: GossipReport,
+}
+
+/// The object that is printed for every slot, and at the end, if `--json-output` is set.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonOutput {
+    Slot {
+        slot: u64,
+        /// The block fields are `null` for empty slots.
+        block_number: Option<u64>,
+        block_hash: Option<B256>,
+        state_root: Option<B256>,
+        gossiped: usize,
+        gossiped_bytes: usize,
+        skipped: usize,
+        elapsed_ms: u128,
+    },
+    Summary {
+        last_slot: u64,
+        blocks: usize,
+        empty_slots: usize,
+        gossiped: usize,
+        skipped: usize,
+        elapsed_ms: u128,
+        lag_ms: u128,
+        failed_slots: Vec<u64>,
+    },
+}
+
+impl JsonOutput {
+    fn slot(slot: u64, slot_report: Option<&SlotReport>) -> Self {
+        let gossip_report = slot_report.map(|slot_report| &slot_report.gossip);
+        Self::Slot {
+            slot,
+            block_number: slot_report.map(|slot_report| slot_report.block_number),
+            block_hash: slot_report.map(|slot_report| slot_report.block_hash),
+            state_root: slot_report.map(|slot_report| slot_report.state_root),
+            gossiped: gossip_report.map_or(0, |report| report.gossiped.len()),
+            gossiped_bytes: gossip_report.map_or(0, |report| {
+                report
+                    .gossiped
+                    .iter()
+                    .map(|content| content.value_size)
+                    .sum()
+            }),
+            skipped: gossip_report.map_or(0, |report| report.skipped),
+            elapsed_ms: gossip_report.map_or(0, |report| report.elapsed.as_millis()),
+        }
+    }
+
+    fn summary(
+        last_slot: u64,
+        summary: &GossipSummary,
+        elapsed: Duration,
+        lag: Duration,
+        failed_slots: Vec<u64>,
+    ) -> Self {
+        Self::Summary {
+            last_slot,
+            blocks: summary.blocks,
+            empty_slots: summary.empty_slots,
+            gossiped: summary.gossiped,
+            skipped: summary.skipped,
+            elapsed_ms: elapsed.as_millis(),
+            lag_ms: lag.as_millis(),
+            failed_slots,
+        }
+    }
+
+    fn print(&self) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string(self)?);
+        Ok(())
+    }
+}
+
+/// Remembers the value hashes of the most recently gossiped content.
+///
+/// The value hashes are computed without the proof, as the proof commits to the block and would
+/// differ for identical content gossiped in different blocks.
+struct GossipCache {
+    capacity: usize,
+    /// Content id to value hash and the "time" of the last use.
+    entries: HashMap<B256, (B256, u64)>,
+    /// The "time" of the last use to content id.
+    last_used: BTreeMap<u64, B256>,
+    time: u64,
+}
+
+impl GossipCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            last_used: BTreeMap::new(),
+            time: 0,
+        }
+    }
+
+    /// Returns whether content with identical value was gossiped, marking it as recently used.
+    fn contains(&mut self, content_id: B256, value_hash: B256) -> bool {
+        match self.entries.get(&content_id) {
+            Some((cached_value_hash, _)) if *cached_value_hash == value_hash => {
+                self.insert(content_id, value_hash);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn insert(&mut self, content_id: B256, value_hash: B256) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.time += 1;
+        if let Some((_, last_used)) = self.entries.insert(content_id, (value_hash, self.time)) {
+            self.last_used.remove(&last_used);
+        }
+        self.last_used.insert(self.time, content_id);
+        if self.entries.len() > self.capacity {
+            if let Some((_, evicted)) = self.last_used.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+}
+
+/// The record written to the `--log-gossiped-keys` file.
+#[derive(Serialize)]
+struct GossipedContentRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slot: Option<u64>,
+    block_hash: B256,
+    content_key: String,
+    value_size: usize,
 }
 
 struct BranchNodeBuilderWithFragments<'a> {
     builder: PortalBranchNodeBuilder<'a>,
-    fragment_indices: HashSet<u8>,
+    fragment_indices: BTreeSet<u8>,
 }
 
 struct LeafNodeBuilderWithFragments<'a> {
     builder: PortalLeafNodeBuilder<'a>,
-    fragment_indices: HashSet<u8>,
+    fragment_indices: BTreeSet<u8>,
+}
+
+impl BranchNodeBuilderWithFragments<'_> {
+    /// Creates content keys and values of the bundle and fragment nodes that should be gossiped.
+    fn content(&self, block_hash: B256) -> Vec<(VerkleContentKey, VerkleContentValue)> {
+        let mut content = vec![];
+
+        // Bundle
+        let bundle_key = VerkleContentKey::Bundle(self.builder.bundle_commitment().clone());
+        let bundle_value =
+            VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::BranchBundle(
+                self.builder.bundle_node_with_proof(block_hash),
+            ));
+        content.push((bundle_key, bundle_value));
+
+        // Fragments
+        for &fragment_index in &self.fragment_indices {
+            let fragment_key = VerkleContentKey::BranchFragment(
+                self.builder.fragment_commitment(fragment_index).clone(),
+            );
+            let fragment_value =
+                VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::BranchFragment(
+                    self.builder
+                        .fragment_node_with_proof(fragment_index, block_hash),
+                ));
+            content.push((fragment_key, fragment_value));
+        }
+
+        content
+    }
+}
+
+impl LeafNodeBuilderWithFragments<'_> {
+    /// Creates content keys and values of the bundle and fragment nodes that should be gossiped.
+    fn content(&self, block_hash: B256) -> Vec<(VerkleContentKey, VerkleContentValue)> {
+        let mut content = vec![];
+
+        // Bundle
+        let bundle_key = VerkleContentKey::Bundle(self.builder.bundle_commitment().clone());
+        let bundle_value = VerkleContentValue::NodeWithProof(
+            PortalVerkleNodeWithProof::LeafBundle(self.builder.bundle_node_with_proof(block_hash)),
+        );
+        content.push((bundle_key, bundle_value));
+
+        // Fragments
+        for &fragment_index in &self.fragment_indices {
+            let fragment_key = VerkleContentKey::LeafFragment(LeafFragmentKey {
+                stem: *self.builder.stem(),
+                commitment: self.builder.fragment_commitment(fragment_index).clone(),
+            });
+            let fragment_value =
+                VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::LeafFragment(
+                    self.builder
+                        .fragment_node_with_proof(fragment_index, block_hash),
+                ));
+            content.push((fragment_key, fragment_value));
+        }
+
+        content
+    }
 }
 
 struct TriePathWrapper(TriePath);
@@ -81,59 +496,315 @@ impl Ord for TriePathWrapper {
     }
 }
 
-struct Gossiper {
-    block_fetcher: BeaconBlockFetcher,
-    portal_client: HttpClient,
+struct Gossiper<T = ResilientPortalClient> {
+    block_fetcher: Arc<BeaconBlockFetcher>,
+    portal_client: T,
     evm: VerkleEvm,
+    genesis_file: PathBuf,
+    genesis_block_hash: B256,
+    state_trie_fetcher: Option<StateTrieFetcher>,
+    verify_sample_size: usize,
+    verify_sample_rate: Option<f64>,
+    /// The random number generator used for sampling (see `--seed`).
+    rng: Mutex<StdRng>,
+    gossiped_keys_log: Option<BufWriter<File>>,
+    /// The last gossiped slot.
+    slot: u64,
+    /// Present only if state trie should be saved (`--output-trie`).
+    trie_snapshot: Option<TrieSnapshotBuilder>,
+    gossip_cache: Mutex<GossipCache>,
+    /// If present, only the writes to this stem are gossiped (see `--filter-address`).
+    filter_stem: Option<Stem>,
+    json_output: bool,
+    /// See `--slot-time-warning-secs`.
+    slot_time: Duration,
+    /// How much the bridge is behind the chain, assuming it was in sync at the first slot.
+    lag: Duration,
+    continue_on_error: bool,
+    /// The slots that failed to gossip, with their errors (see `--continue-on-error`).
+    failed_slots: Vec<(u64, anyhow::Error)>,
 }
 
 impl Gossiper {
     fn new(args: &Args) -> anyhow::Result<Self> {
-        let block_fetcher =
-            BeaconBlockFetcher::new(&args.beacon_rpc_url, /* save_locally = */ false);
-        let portal_client = HttpClientBuilder::new()
-            .request_timeout(Duration::from_secs(60))
-            .build(&args.portal_rpc_url)?;
-        let evm = VerkleEvm::new(read_genesis()?)?;
+        let portal_client =
+            ResilientPortalClient::new(&args.portal_rpc_url, args.portal_client.config())?;
+        Self::with_transport(args, portal_client)
+    }
+}
+
+impl<T: PortalTransport> Gossiper<T> {
+    fn with_transport(args: &Args, portal_client: T) -> anyhow::Result<Self> {
+        let block_fetcher = Arc::new(match args.beacon_source {
+            BeaconSource::Rpc => {
+                BeaconBlockFetcher::new(&args.beacon_rpc_url, /* save_locally = */ false)
+            }
+            BeaconSource::Files => BeaconBlockFetcher::from_files(),
+        });
+        let (mut evm, slot, trie_snapshot) = match &args.input_trie {
+            Some(input_trie) => {
+                let snapshot = TrieSnapshot::read_from_file(input_trie)?;
+                print_info!(
+                    args.json_output,
+                    "Loaded state trie at slot {} (block {})",
+                    snapshot.slot,
+                    snapshot.block_number
+                );
+                let mut evm = VerkleEvm::new_from_trie(
+                    snapshot.to_state_trie()?,
+                    snapshot.block_number,
+                    snapshot.state_root,
+                )?;
+                if let Some(block_hash) = snapshot.block_hash {
+                    evm.set_block_hash(block_hash);
+                }
+                let slot = snapshot.slot;
+                let trie_snapshot = args
+                    .output_trie
+                    .as_ref()
+                    .map(|_| TrieSnapshotBuilder::from(snapshot));
+                (evm, slot, trie_snapshot)
+            }
+            None => {
+                let mut evm = VerkleEvm::new(read_genesis_from_file(&args.genesis_file)?)?;
+                evm.set_block_hash(args.genesis_block_hash);
+                let trie_snapshot = args
+                    .output_trie
+                    .as_ref()
+                    .map(|_| TrieSnapshotBuilder::new());
+                (evm, 0, trie_snapshot)
+            }
+        };
+        evm.set_state_root_diagnostics(args.diagnose_state_root);
+        let state_trie_fetcher = if args.verify_after_gossip {
+            Some(StateTrieFetcher::new(
+                &args.portal_rpc_url,
+                args.portal_client.config(),
+            )?)
+        } else {
+            None
+        };
+        let gossiped_keys_log = match &args.log_gossiped_keys {
+            Some(path) => Some(BufWriter::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
 
         Ok(Self {
             block_fetcher,
             portal_client,
             evm,
+            genesis_file: args.genesis_file.clone(),
+            genesis_block_hash: args.genesis_block_hash,
+            state_trie_fetcher,
+            verify_sample_size: args.verify_sample_size,
+            verify_sample_rate: args.verify_sample_rate,
+            rng: Mutex::new(match args.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            }),
+            gossiped_keys_log,
+            slot,
+            trie_snapshot,
+            gossip_cache: Mutex::new(GossipCache::new(args.gossip_cache_size)),
+            filter_stem: args
+                .filter_address
+                .map(|address| *AccountStorageLayout::new(address).account_storage_stem()),
+            json_output: args.json_output,
+            slot_time: Duration::from_secs(args.slot_time_warning_secs),
+            lag: Duration::ZERO,
+            continue_on_error: args.continue_on_error,
+            failed_slots: vec![],
         })
     }
 
-    async fn gossip_genesis(&mut self) -> anyhow::Result<()> {
-        let state_writes = read_genesis()?.into_state_writes();
-        println!("Gossiping genesis...");
-        self.gossip_state_writes(
-            GenesisConfig::DEVNET6_BLOCK_HASH,
-            state_writes,
-            HashSet::new(),
-        )
-        .await?;
+    async fn gossip_genesis(&mut self) -> anyhow::Result<SlotReport> {
+        let state_writes = read_genesis_from_file(&self.genesis_file)?.into_state_writes();
+        if let Some(trie_snapshot) = &mut self.trie_snapshot {
+            trie_snapshot.update(&state_writes);
+        }
+        print_info!(self.json_output, "Gossiping genesis...");
+        let report = self
+            .gossip_state_writes(self.genesis_block_hash, state_writes, HashSet::new())
+            .await?;
+        self.log_gossiped_content(
+            /* slot= */ Some(0),
+            self.genesis_block_hash,
+            &report.gossiped,
+        )?;
+        Ok(SlotReport {
+            block_number: 0,
+            block_hash: self.genesis_block_hash,
+            state_root: self.evm.state_trie().root(),
+            gossip: report,
+        })
+    }
+
+    /// Starts the background task that fetches beacon blocks (in order) ahead of the slot that is
+    /// being gossiped.
+    ///
+    /// The number of blocks ahead is bounded by the fetching concurrency (`prefetch_depth`), as
+    /// the channel only holds the next block.
+    fn prefetch_beacon_blocks(
+        &self,
+        slots: Range<u64>,
+        prefetch_depth: usize,
+    ) -> mpsc::Receiver<(u64, anyhow::Result<Option<SignedBeaconBlock>>)> {
+        let (sender, receiver) = mpsc::channel(1);
+        let block_fetcher = self.block_fetcher.clone();
+        tokio::spawn(async move {
+            let mut beacon_blocks = pin!(block_fetcher.fetch_block_range(slots, prefetch_depth));
+            while let Some(beacon_block) = beacon_blocks.next().await {
+                if sender.send(beacon_block).await.is_err() {
+                    // Receiver is dropped, nothing else to do
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    /// Processes the beacon block at the given slot, without gossiping its state changes.
+    fn process_slot(
+        &mut self,
+        slot: u64,
+        beacon_block: Option<SignedBeaconBlock>,
+    ) -> anyhow::Result<()> {
+        if let Some(beacon_block) = beacon_block {
+            self.process_block(&beacon_block.message.body.execution_payload)?;
+        }
+        self.slot = slot;
         Ok(())
     }
 
-    async fn gossip_slot(&mut self, slot: u64) -> anyhow::Result<()> {
-        let Ok(Some(beacon_block)) = self.block_fetcher.fetch_beacon_block(slot).await else {
-            println!("Beacon block for slot {slot} not found!");
-            return Ok(());
+    /// Processes the block and keeps track of its state writes, if needed.
+    fn process_block(
+        &mut self,
+        execution_payload: &ExecutionPayload,
+    ) -> anyhow::Result<ProcessBlockResult> {
+        let process_block_result = self.evm.process_block(execution_payload)?;
+        if let Some(trie_snapshot) = &mut self.trie_snapshot {
+            trie_snapshot.update(&process_block_result.state_writes);
+        }
+        Ok(process_block_result)
+    }
+
+    /// Gossips the state changes of the execution witness from the file (see `--witness-file`).
+    ///
+    /// The proof of the witness can't be verified yet, so the witness is rejected before
+    /// anything is applied or gossiped.
+    async fn gossip_witness(
+        &mut self,
+        path: &Path,
+        block_hash: B256,
+    ) -> anyhow::Result<GossipReport> {
+        let reader = BufReader::new(File::open(path)?);
+        let execution_witness: ExecutionWitness = serde_json::from_reader(reader)
+            .with_context(|| format!("Invalid execution witness file: {path:?}"))?;
+        execution_witness.validate()?;
+        // Once supported: verify the proof against the pre-state root, then apply the witness with
+        // `VerkleEvm::apply_witness` and gossip its state writes.
+        bail!(
+            "Can't gossip the execution witness of block {block_hash}: verifying its proof isn't \
+            supported yet"
+        )
+    }
+
+    /// Gossips the state changes of the beacon block at the given slot.
+    ///
+    /// Returns `None` if the slot is empty.
+    async fn gossip_slot(
+        &mut self,
+        slot: u64,
+        beacon_block: Option<SignedBeaconBlock>,
+    ) -> anyhow::Result<Option<SlotReport>> {
+        let timer = Instant::now();
+        let Some(beacon_block) = beacon_block else {
+            print_info!(self.json_output, "Beacon block for slot {slot} not found!");
+            self.slot = slot;
+            self.track_lag(slot, timer.elapsed());
+            return Ok(None);
         };
         let execution_payload = &beacon_block.message.body.execution_payload;
-        let process_block_result = self.evm.process_block(execution_payload)?;
-        println!(
+        let process_block_result = self.process_block(execution_payload)?;
+        print_info!(
+            self.json_output,
             "Gossiping slot {slot:04} (block - number={:04} hash={} root={})",
             execution_payload.block_number,
             execution_payload.block_hash,
             execution_payload.state_root
         );
-        self.gossip_state_writes(
-            execution_payload.block_hash,
-            process_block_result.state_writes,
-            process_block_result.new_branch_nodes,
-        )
-        .await?;
+        let stats = process_block_result.stats;
+        print_info!(
+            self.json_output,
+            "  stems: {}, writes: {} (new: {}, updated: {})",
+            stats.stems,
+            stats.suffix_writes,
+            stats.new_values,
+            stats.updated_values
+        );
+        let report = match self
+            .gossip_state_writes(
+                execution_payload.block_hash,
+                process_block_result.state_writes,
+                process_block_result.new_branch_nodes,
+            )
+            .await
+        {
+            Ok(report) => report,
+            // The state is already updated, so the following slots can still be gossiped
+            Err(err) if self.continue_on_error => {
+                eprintln!("ERROR: Failed to gossip slot {slot}: {err:#}");
+                self.failed_slots.push((slot, err));
+                GossipReport::default()
+            }
+            Err(err) => return Err(err),
+        };
+        self.log_gossiped_content(Some(slot), execution_payload.block_hash, &report.gossiped)?;
+        self.slot = slot;
+        self.track_lag(slot, timer.elapsed());
+        Ok(Some(SlotReport {
+            block_number: execution_payload.block_number.to(),
+            block_hash: execution_payload.block_hash,
+            state_root: execution_payload.state_root,
+            gossip: report,
+        }))
+    }
+
+    /// Updates how much the bridge is behind the chain, given how long the slot took.
+    ///
+    /// Logs a warning if the slot took longer than the slot time. Faster slots (e.g. empty ones)
+    /// reduce the lag.
+    fn track_lag(&mut self, slot: u64, elapsed: Duration) {
+        self.lag = (self.lag + elapsed).saturating_sub(self.slot_time);
+        if elapsed > self.slot_time {
+            eprintln!(
+                "WARNING: Slot {slot} took {elapsed:?}, longer than the slot time of {:?}. The \
+                bridge is lagging behind the chain by {:?}",
+                self.slot_time, self.lag
+            );
+        }
+    }
+
+    /// Saves the state trie after the last gossiped slot (see `--output-trie`).
+    fn write_trie_snapshot(&self, path: &Path) -> anyhow::Result<()> {
+        let Some(trie_snapshot) = &self.trie_snapshot else {
+            bail!("State trie is not tracked")
+        };
+        trie_snapshot
+            .build(
+                self.slot,
+                self.evm.block(),
+                self.evm.block_hash(),
+                self.evm.state_trie().root(),
+            )
+            .write_to_file(path)?;
+        print_info!(
+            self.json_output,
+            "Saved state trie at slot {} to {path:?}",
+            self.slot
+        );
         Ok(())
     }
 
@@ -142,76 +813,63 @@ impl Gossiper {
         block_hash: B256,
         state_writes: StateWrites,
         new_branch_nodes: HashSet<TriePath>,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<GossipReport> {
         let timer = Instant::now();
 
-        let mut branches_to_gossip: BTreeMap<TriePathWrapper, BranchNodeBuilderWithFragments> =
-            BTreeMap::new();
-        let mut leaves_to_gossip: BTreeMap<Stem, LeafNodeBuilderWithFragments> = BTreeMap::new();
-
-        for stem_state_write in state_writes.iter() {
-            let stem = &stem_state_write.stem;
-            let path_to_leaf = self.evm.state_trie().traverse_to_leaf(stem)?;
-
-            for depth in 0..path_to_leaf.trie_path.len() {
-                let trie_path = TriePath::from(stem[..depth].to_vec());
-                let (branch, child_index) = path_to_leaf.trie_path[depth];
-
-                branches_to_gossip
-                    .entry(TriePathWrapper(trie_path))
-                    .or_insert_with_key(|trie_path| {
-                        let builder =
-                            PortalBranchNodeBuilder::new(branch, &path_to_leaf.trie_path[..depth])
-                                .expect("creating PortalBranchNodeBuilder should succeed");
-                        let fragment_indices = if new_branch_nodes.contains(&trie_path.0) {
-                            HashSet::from_iter((0..PORTAL_NETWORK_NODE_WIDTH as u8).filter(
-                                |fragment_index| {
-                                    !builder.fragment_commitment(*fragment_index).is_zero()
-                                },
-                            ))
-                        } else {
-                            HashSet::new()
-                        };
-                        BranchNodeBuilderWithFragments {
-                            builder,
-                            fragment_indices,
-                        }
-                    })
-                    .fragment_indices
-                    .insert(child_index / PORTAL_NETWORK_NODE_WIDTH as u8);
+        let state_writes = match &self.filter_stem {
+            Some(filter_stem) => {
+                let state_writes = StateWrites::new(
+                    state_writes
+                        .iter()
+                        .filter(|stem_state_write| &stem_state_write.stem == filter_stem)
+                        .cloned()
+                        .collect(),
+                );
+                if state_writes.iter().next().is_none() {
+                    print_info!(
+                        self.json_output,
+                        "No state writes for filtered address, skipping"
+                    );
+                    return Ok(GossipReport::default());
+                }
+                state_writes
             }
+            None => state_writes,
+        };
 
-            leaves_to_gossip
-                .entry(*stem)
-                .or_insert_with(|| {
-                    let builder = PortalLeafNodeBuilder::new(&path_to_leaf);
-                    LeafNodeBuilderWithFragments {
-                        builder,
-                        fragment_indices: HashSet::new(),
-                    }
-                })
-                .fragment_indices
-                .extend(
-                    stem_state_write
-                        .writes
-                        .keys()
-                        .map(|child_index| child_index / PORTAL_NETWORK_NODE_WIDTH as u8)
-                        .dedup(),
-                );
-        }
+        let (branches_to_gossip, leaves_to_gossip) =
+            nodes_to_gossip(self.evm.state_trie(), &state_writes, &new_branch_nodes)?;
+
+        let mut report = GossipReport::default();
 
         for (trie_path, builder_with_fragments) in branches_to_gossip.into_iter() {
-            self.gossip_branch_node(trie_path.0, builder_with_fragments, block_hash)
-                .await?;
+            report.extend(
+                self.gossip_branch_node(trie_path.0, builder_with_fragments, block_hash)
+                    .await?,
+            );
         }
 
         for builder_with_fragments in leaves_to_gossip.into_values() {
-            self.gossip_leaf_node(builder_with_fragments, block_hash)
-                .await?;
+            report.extend(
+                self.gossip_leaf_node(builder_with_fragments, block_hash)
+                    .await?,
+            );
         }
 
-        println!("Elapsed: {:?}", timer.elapsed());
-        Ok(())
+        report.elapsed = timer.elapsed();
+        print_info!(
+            self.json_output,
+            "Elapsed: {:?} (gossiped: {}, skipped: {})",
+            report.elapsed,
+            report.gossiped.len(),
+            report.skipped
+        );
+
+        if let Some(state_trie_fetcher) = &self.state_trie_fetcher {
+            self.verify_gossiped_content(state_trie_fetcher, &report.gossiped)
+                .await;
+        }
+        Ok(report)
     }
 
     async fn gossip_branch_node(
@@ -219,100 +877,759 @@ impl Gossiper {
         trie_path: TriePath,
         builder_with_fragments: BranchNodeBuilderWithFragments<'_>,
         block_hash: B256,
-    ) -> anyhow::Result<()> {
-        let BranchNodeBuilderWithFragments {
-            builder,
-            fragment_indices,
-        } = builder_with_fragments;
-        println!(
+    ) -> anyhow::Result<GossipReport> {
+        print_info!(
+            self.json_output,
             "  branch: 0x{} children: {:x?}",
             trie_path.into_iter().map(|i| format!("{i:x}")).join(""),
-            fragment_indices.iter().sorted().collect_vec()
+            builder_with_fragments.fragment_indices
         );
 
-        let mut gossip_futures = vec![];
+        self.gossip_content(builder_with_fragments.content(block_hash))
+            .await
+    }
 
-        // Gossip bundle
-        let bundle_key = VerkleContentKey::Bundle(builder.bundle_commitment().clone());
-        let bundle_value = VerkleContentValue::NodeWithProof(
-            PortalVerkleNodeWithProof::BranchBundle(builder.bundle_node_with_proof(block_hash)),
+    async fn gossip_leaf_node(
+        &self,
+        builder_with_fragments: LeafNodeBuilderWithFragments<'_>,
+        block_hash: B256,
+    ) -> anyhow::Result<GossipReport> {
+        print_info!(
+            self.json_output,
+            "  leaf: {} children: {:x?}",
+            builder_with_fragments.builder.stem(),
+            builder_with_fragments.fragment_indices
         );
-        gossip_futures.push(self.portal_client.gossip(bundle_key, bundle_value));
 
-        // Gossip fragments
-        for fragment_index in fragment_indices {
-            let fragment_key = VerkleContentKey::BranchFragment(
-                builder.fragment_commitment(fragment_index).clone(),
-            );
-            let fragment_value =
-                VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::BranchFragment(
-                    builder.fragment_node_with_proof(fragment_index, block_hash),
-                ));
-            gossip_futures.push(self.portal_client.gossip(fragment_key, fragment_value));
+        self.gossip_content(builder_with_fragments.content(block_hash))
+            .await
+    }
+
+    /// Gossips all content concurrently, skipping content that is already gossiped (see
+    /// `--gossip-cache-size`).
+    ///
+    /// Returns the keys and sizes of the gossiped content.
+    async fn gossip_content(
+        &self,
+        content: Vec<(VerkleContentKey, VerkleContentValue)>,
+    ) -> anyhow::Result<GossipReport> {
+        let mut skipped = 0;
+        let content = {
+            let mut gossip_cache = self
+                .gossip_cache
+                .lock()
+                .expect("gossip cache lock shouldn't be poisoned");
+            content
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let content_id = B256::from(key.content_id());
+                    let value_hash = keccak256(strip_proof(value.clone()).encode());
+                    if gossip_cache.contains(content_id, value_hash) {
+                        skipped += 1;
+                        None
+                    } else {
+                        let value_size = value.encode().len();
+                        Some((key, value, value_size, content_id, value_hash))
+                    }
+                })
+                .collect_vec()
+        };
+
+        let results = future::join_all(
+            content
+                .iter()
+                .map(|(key, value, ..)| self.portal_client.gossip(key.clone(), value.clone())),
+        )
+        .await;
+        let failed_keys = zip_eq(&content, results)
+            .filter_map(|((key, ..), result)| result.is_err().then(|| key.to_hex()))
+            .collect_vec();
+        if !failed_keys.is_empty() {
+            bail!(
+                "Failed to gossip content. Keys: [{}]",
+                failed_keys.join(", ")
+            )
         }
 
-        future::try_join_all(gossip_futures).await?;
+        let mut gossip_cache = self
+            .gossip_cache
+            .lock()
+            .expect("gossip cache lock shouldn't be poisoned");
+        let gossiped = content
+            .into_iter()
+            .map(|(key, _, value_size, content_id, value_hash)| {
+                gossip_cache.insert(content_id, value_hash);
+                GossipedContent { key, value_size }
+            })
+            .collect();
+        Ok(GossipReport {
+            gossiped,
+            skipped,
+            ..Default::default()
+        })
+    }
 
-        Ok(())
+    /// Returns the random sample of the gossiped content, as configured by `--verify-sample-size`
+    /// or `--verify-sample-rate`.
+    fn sample_gossiped_content<'a>(
+        &self,
+        gossiped_content: &'a [GossipedContent],
+    ) -> Vec<&'a GossipedContent> {
+        let sample_size = match self.verify_sample_rate {
+            Some(sample_rate) => (sample_rate * gossiped_content.len() as f64).ceil() as usize,
+            None => self.verify_sample_size,
+        };
+        let mut rng = self.rng.lock().expect("rng lock shouldn't be poisoned");
+        gossiped_content
+            .choose_multiple(&mut *rng, sample_size)
+            .collect_vec()
     }
 
-    async fn gossip_leaf_node(
+    /// Fetches a random sample of the gossiped content and reports how much of it was found.
+    async fn verify_gossiped_content(
         &self,
-        builder_with_fragments: LeafNodeBuilderWithFragments<'_>,
+        state_trie_fetcher: &StateTrieFetcher,
+        gossiped_content: &[GossipedContent],
+    ) {
+        let sample = self.sample_gossiped_content(gossiped_content);
+        if sample.is_empty() {
+            return;
+        }
+
+        let found = future::join_all(
+            sample
+                .iter()
+                .map(|content| state_trie_fetcher.fetch_content(&content.key)),
+        )
+        .await
+        .into_iter()
+        .filter(Result::is_ok)
+        .count();
+        print_info!(
+            self.json_output,
+            "Verified gossiped content: found {found}/{} ({:.1}%), sampled from {} gossiped",
+            sample.len(),
+            100.0 * found as f64 / sample.len() as f64,
+            gossiped_content.len()
+        );
+    }
+
+    /// Appends the gossiped content to the `--log-gossiped-keys` file, if set.
+    fn log_gossiped_content(
+        &mut self,
+        slot: Option<u64>,
         block_hash: B256,
+        gossiped_content: &[GossipedContent],
     ) -> anyhow::Result<()> {
-        let LeafNodeBuilderWithFragments {
-            builder,
-            fragment_indices,
-        } = builder_with_fragments;
-        println!(
-            "  leaf: {} children: {:x?}",
-            builder.stem(),
-            fragment_indices.iter().sorted().collect_vec()
-        );
+        let Some(writer) = &mut self.gossiped_keys_log else {
+            return Ok(());
+        };
+        for content in gossiped_content {
+            let record = GossipedContentRecord {
+                slot,
+                block_hash,
+                content_key: content.key.to_hex(),
+                value_size: content.value_size,
+            };
+            serde_json::to_writer(&mut *writer, &record)?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
 
-        let mut gossip_futures = vec![];
+type BranchesToGossip<'a> = BTreeMap<TriePathWrapper, BranchNodeBuilderWithFragments<'a>>;
+type LeavesToGossip<'a> = BTreeMap<Stem, LeafNodeBuilderWithFragments<'a>>;
 
-        // Gossip bundle
-        let bundle_key = VerkleContentKey::Bundle(builder.bundle_commitment().clone());
-        let bundle_value = VerkleContentValue::NodeWithProof(
-            PortalVerkleNodeWithProof::LeafBundle(builder.bundle_node_with_proof(block_hash)),
-        );
-        gossip_futures.push(self.portal_client.gossip(bundle_key, bundle_value));
+/// Finds all branch and leaf nodes (and their fragments) that are affected by the state writes.
+fn nodes_to_gossip<'a>(
+    state_trie: &'a VerkleTrie,
+    state_writes: &StateWrites,
+    new_branch_nodes: &HashSet<TriePath>,
+) -> anyhow::Result<(BranchesToGossip<'a>, LeavesToGossip<'a>)> {
+    let mut branches_to_gossip = BranchesToGossip::new();
+    let mut leaves_to_gossip = LeavesToGossip::new();
 
-        // Gossip fragments
-        for fragment_index in fragment_indices {
-            let fragment_key = VerkleContentKey::LeafFragment(LeafFragmentKey {
-                stem: *builder.stem(),
-                commitment: builder.fragment_commitment(fragment_index).clone(),
-            });
-            let fragment_value =
-                VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::LeafFragment(
-                    builder.fragment_node_with_proof(fragment_index, block_hash),
-                ));
-            gossip_futures.push(self.portal_client.gossip(fragment_key, fragment_value));
-        }
+    for stem_state_write in state_writes.iter() {
+        let stem = &stem_state_write.stem;
+        let path_to_leaf = state_trie.traverse_to_leaf(stem)?;
 
-        future::try_join_all(gossip_futures).await?;
+        for depth in 0..path_to_leaf.trie_path.len() {
+            let trie_path = TriePath::from(stem[..depth].to_vec());
+            let (branch, child_index) = path_to_leaf.trie_path[depth];
 
-        Ok(())
+            let builder_with_fragments = match branches_to_gossip.entry(TriePathWrapper(trie_path))
+            {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let builder =
+                        PortalBranchNodeBuilder::new(branch, &path_to_leaf.trie_path[..depth])
+                            .with_context(|| {
+                                format!(
+                                    "Failed to create PortalBranchNodeBuilder for branch: 0x{}",
+                                    entry.key().0.iter().map(|i| format!("{i:x}")).join("")
+                                )
+                            })?;
+                    let fragment_indices = if new_branch_nodes.contains(&entry.key().0) {
+                        BTreeSet::from_iter((0..PORTAL_NETWORK_NODE_WIDTH as u8).filter(
+                            |fragment_index| {
+                                !builder.fragment_commitment(*fragment_index).is_zero()
+                            },
+                        ))
+                    } else {
+                        BTreeSet::new()
+                    };
+                    entry.insert(BranchNodeBuilderWithFragments {
+                        builder,
+                        fragment_indices,
+                    })
+                }
+            };
+            builder_with_fragments
+                .fragment_indices
+                .insert(child_index / PORTAL_NETWORK_NODE_WIDTH as u8);
+        }
+
+        leaves_to_gossip
+            .entry(*stem)
+            .or_insert_with(|| {
+                let builder = PortalLeafNodeBuilder::new(&path_to_leaf);
+                LeafNodeBuilderWithFragments {
+                    builder,
+                    fragment_indices: BTreeSet::new(),
+                }
+            })
+            .fragment_indices
+            .extend(
+                stem_state_write
+                    .writes
+                    .keys()
+                    .map(|child_index| child_index / PORTAL_NETWORK_NODE_WIDTH as u8)
+                    .dedup(),
+            );
     }
+
+    Ok((branches_to_gossip, leaves_to_gossip))
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    println!("Initializing...");
+    print_info!(args.json_output, "Initializing...");
     let mut gossiper = Gossiper::new(&args)?;
 
-    println!("Starting gossiping");
+    if let (Some(witness_file), Some(witness_block_hash)) =
+        (&args.witness_file, args.witness_block_hash)
+    {
+        let report = gossiper
+            .gossip_witness(witness_file, witness_block_hash)
+            .await?;
+        print_info!(
+            args.json_output,
+            "Finished gossiping witness: {} nodes gossiped ({} skipped as already gossiped)",
+            report.gossiped.len(),
+            report.skipped
+        );
+        return Ok(());
+    }
+
+    // The slots to gossip: [first_slot, end_slot)
+    let next_slot = gossiper.slot + 1;
+    let (first_slot, end_slot) = match args.epoch {
+        Some(epoch) => {
+            let first_slot = epoch * SLOTS_PER_EPOCH;
+            // Slot 0 is genesis
+            if first_slot.max(1) < next_slot {
+                bail!(
+                    "Can't gossip epoch {epoch}, state is already at slot {}",
+                    gossiper.slot
+                );
+            }
+            (first_slot, first_slot + SLOTS_PER_EPOCH)
+        }
+        None => {
+            let slots = args.slots.unwrap_or(MAX_SLOTS_UNTIL_BLOCK_HASH);
+            (next_slot, next_slot + slots)
+        }
+    };
+
+    print_info!(args.json_output, "Starting gossiping");
     let timer = Instant::now();
-    gossiper.gossip_genesis().await?;
-    for slot in 1..=args.slots {
-        gossiper.gossip_slot(slot).await?;
+    let mut epoch_summary = GossipSummary::default();
+    let mut total_summary = GossipSummary::default();
+    // Genesis is gossiped only if there are no slots to process before the first slot
+    if args.input_trie.is_none() && first_slot <= next_slot {
+        let slot_report = gossiper.gossip_genesis().await?;
+        if args.json_output {
+            JsonOutput::slot(/* slot= */ 0, Some(&slot_report)).print()?;
+        }
+        epoch_summary.add(Some(&slot_report.gossip));
+        total_summary.add(Some(&slot_report.gossip));
+    }
+    let shutdown_requested = handle_ctrl_c();
+    let mut reached_block_hash = false;
+    let mut beacon_blocks =
+        gossiper.prefetch_beacon_blocks(next_slot..end_slot, args.prefetch_depth);
+    while let Some((slot, beacon_block)) = beacon_blocks.recv().await {
+        if shutdown_requested.load(atomic::Ordering::Relaxed) {
+            break;
+        }
+        // The following blocks can't be processed without this one, so the fetching error stops
+        // gossiping, even with `--continue-on-error`
+        let beacon_block = beacon_block
+            .with_context(|| format!("Failed to fetch the beacon block for slot {slot}"))?;
+        if slot < first_slot {
+            gossiper.process_slot(slot, beacon_block)?;
+            continue;
+        }
+        let slot_report = gossiper.gossip_slot(slot, beacon_block).await?;
+        if args.json_output {
+            JsonOutput::slot(slot, slot_report.as_ref()).print()?;
+        }
+        let gossip_report = slot_report.as_ref().map(|slot_report| &slot_report.gossip);
+        epoch_summary.add(gossip_report);
+        total_summary.add(gossip_report);
+        if (slot + 1) % SLOTS_PER_EPOCH == 0 {
+            if !args.json_output {
+                epoch_summary.print_epoch(slot / SLOTS_PER_EPOCH);
+            }
+            epoch_summary = GossipSummary::default();
+        }
+        let block_hash = slot_report.map(|slot_report| slot_report.block_hash);
+        if args.until_block_hash.is_some() && block_hash == args.until_block_hash {
+            reached_block_hash = true;
+            break;
+        }
+    }
+    if !args.json_output && (gossiper.slot + 1) % SLOTS_PER_EPOCH != 0 {
+        epoch_summary.print_epoch(gossiper.slot / SLOTS_PER_EPOCH);
+    }
+    if shutdown_requested.load(atomic::Ordering::Relaxed) {
+        eprintln!(
+            "Stopped. Last fully gossiped slot: {}. Use --output-trie and --input-trie to \
+            resume after it.",
+            gossiper.slot
+        );
+    } else if let Some(until_block_hash) = args.until_block_hash {
+        if !reached_block_hash {
+            bail!(
+                "Block {until_block_hash} not reached within {} slots",
+                end_slot - first_slot
+            );
+        }
+    }
+    if args.json_output {
+        JsonOutput::summary(
+            gossiper.slot,
+            &total_summary,
+            timer.elapsed(),
+            gossiper.lag,
+            gossiper
+                .failed_slots
+                .iter()
+                .map(|(slot, _)| *slot)
+                .collect(),
+        )
+        .print()?;
+    } else {
+        println!(
+            "Finished gossiping in {:?} (lagging behind the chain by {:?})",
+            timer.elapsed(),
+            gossiper.lag
+        );
+    }
+    if let Some(output_trie) = &args.output_trie {
+        gossiper.write_trie_snapshot(output_trie)?;
+    }
+    if !gossiper.failed_slots.is_empty() {
+        for (slot, err) in &gossiper.failed_slots {
+            eprintln!("Failed slot {slot}: {err:#}");
+        }
+        bail!("Failed to gossip {} slots", gossiper.failed_slots.len());
     }
-    println!("Finished gossiping in {:?}", timer.elapsed());
 
     Ok(())
 }
+
+/// Starts the task that handles Ctrl-C.
+///
+/// The first Ctrl-C sets the returned flag, which stops gossiping after the current slot. The
+/// second one exits immediately.
+fn handle_ctrl_c() -> Arc<AtomicBool> {
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+    let flag = shutdown_requested.clone();
+    tokio::spawn(async move {
+        if signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("Stopping after the current slot. Press Ctrl-C again to abort immediately.");
+        flag.store(true, atomic::Ordering::Relaxed);
+        if signal::ctrl_c().await.is_ok() {
+            eprintln!("Aborting");
+            process::exit(130);
+        }
+    });
+    shutdown_requested
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs::File, io::BufReader, path::PathBuf};
+
+    use ethportal_api::types::verkle::ContentInfo;
+    use portal_verkle::{
+        in_memory_portal_node::InMemoryPortalNode, types::SuccessMessage, utils::beacon_slot_path,
+    };
+
+    use super::*;
+
+    fn test_genesis_file() -> PathBuf {
+        PathBuf::from("..").join(genesis_path())
+    }
+
+    /// Returns the path of the temporary file that is unique to the test (and the process).
+    fn test_temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("portal_verkle_{}_{name}", std::process::id()))
+    }
+
+    /// Parses the args that use the test genesis file, followed by the `extra` args.
+    fn test_args<S: AsRef<OsStr>>(extra: &[S]) -> Args {
+        let genesis_file = test_genesis_file();
+        let args = [
+            OsStr::new("gossip_to_portal"),
+            OsStr::new("--genesis-file"),
+            genesis_file.as_os_str(),
+        ];
+        Args::parse_from(args.into_iter().chain(extra.iter().map(AsRef::as_ref)))
+    }
+
+    fn read_beacon_block(slot: u64) -> anyhow::Result<SignedBeaconBlock> {
+        let reader = BufReader::new(File::open(
+            PathBuf::from("..").join(beacon_slot_path(slot)),
+        )?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        Ok(response.data)
+    }
+
+    fn content_keys(
+        state_trie: &VerkleTrie,
+        state_writes: &StateWrites,
+        new_branch_nodes: &HashSet<TriePath>,
+    ) -> anyhow::Result<Vec<VerkleContentKey>> {
+        let (branches_to_gossip, leaves_to_gossip) =
+            nodes_to_gossip(state_trie, state_writes, new_branch_nodes)?;
+        Ok(branches_to_gossip
+            .values()
+            .flat_map(|builder_with_fragments| builder_with_fragments.content(B256::ZERO))
+            .chain(
+                leaves_to_gossip
+                    .values()
+                    .flat_map(|builder_with_fragments| builder_with_fragments.content(B256::ZERO)),
+            )
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    #[test]
+    fn content_keys_are_deterministic() -> anyhow::Result<()> {
+        let mut evm = VerkleEvm::new(read_genesis_from_file(test_genesis_file())?)?;
+
+        let beacon_block = read_beacon_block(1)?;
+        let process_block_result =
+            evm.process_block(&beacon_block.message.body.execution_payload)?;
+
+        let first = content_keys(
+            evm.state_trie(),
+            &process_block_result.state_writes,
+            &process_block_result.new_branch_nodes,
+        )?;
+        let second = content_keys(
+            evm.state_trie(),
+            &process_block_result.state_writes,
+            &process_block_result.new_branch_nodes,
+        )?;
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gossiped_genesis_can_be_fetched() -> anyhow::Result<()> {
+        let args = test_args(&["--slots", "0"]);
+        let portal_node = InMemoryPortalNode::new();
+        let mut gossiper = Gossiper::with_transport(&args, portal_node.clone())?;
+        gossiper.gossip_genesis().await?;
+        assert!(!portal_node.is_empty());
+
+        let state_root = gossiper.evm.state_trie().root();
+        let fetched_trie = StateTrieFetcher::with_transport(portal_node)
+            .fetch_state_trie(state_root)
+            .await?;
+        assert_eq!(fetched_trie.root(), state_root);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identical_content_is_gossiped_once() -> anyhow::Result<()> {
+        let args = test_args(&["--slots", "0"]);
+        let gossiper = Gossiper::with_transport(&args, InMemoryPortalNode::new())?;
+
+        let state_writes = read_genesis_from_file(test_genesis_file())?.into_state_writes();
+        let (branches_to_gossip, _) =
+            nodes_to_gossip(gossiper.evm.state_trie(), &state_writes, &HashSet::new())?;
+        let root_branch = branches_to_gossip
+            .values()
+            .next()
+            .expect("to find root branch");
+
+        // Root branch is unchanged between two blocks, only its proof differs
+        let first = gossiper
+            .gossip_content(root_branch.content(B256::repeat_byte(1)))
+            .await?;
+        let second = gossiper
+            .gossip_content(root_branch.content(B256::repeat_byte(2)))
+            .await?;
+        assert!(!first.gossiped.is_empty());
+        assert_eq!(first.skipped, 0);
+        assert!(second.gossiped.is_empty());
+        assert_eq!(second.skipped, first.gossiped.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn witness_with_unverified_proof_is_not_gossiped() -> anyhow::Result<()> {
+        let execution_payload = read_beacon_block(1)?.message.body.execution_payload;
+        let witness_file = test_temp_file("witness_with_unverified_proof.json");
+        serde_json::to_writer(
+            File::create(&witness_file)?,
+            &execution_payload.execution_witness,
+        )?;
+
+        let block_hash = execution_payload.block_hash.to_string();
+        let args = test_args(&[
+            OsStr::new("--witness-file"),
+            witness_file.as_os_str(),
+            OsStr::new("--witness-block-hash"),
+            OsStr::new(&block_hash),
+        ]);
+        let portal_node = InMemoryPortalNode::new();
+        let mut gossiper = Gossiper::with_transport(&args, portal_node.clone())?;
+        let root = gossiper.evm.state_trie().root();
+        let report = gossiper
+            .gossip_witness(&witness_file, execution_payload.block_hash)
+            .await;
+        std::fs::remove_file(&witness_file)?;
+
+        assert!(report.is_err());
+        assert!(portal_node.is_empty());
+        assert_eq!(gossiper.evm.state_trie().root(), root);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn input_trie_restores_block_hash() -> anyhow::Result<()> {
+        let trie_file = test_temp_file("input_trie_restores_block_hash.json");
+        let args = test_args(&[
+            OsStr::new("--slots"),
+            OsStr::new("1"),
+            OsStr::new("--output-trie"),
+            trie_file.as_os_str(),
+        ]);
+        let mut gossiper = Gossiper::with_transport(&args, InMemoryPortalNode::new())?;
+        gossiper.gossip_genesis().await?;
+        let slot_report = gossiper
+            .gossip_slot(1, Some(read_beacon_block(1)?))
+            .await?
+            .expect("slot 1 should have a block");
+        gossiper.write_trie_snapshot(&trie_file)?;
+
+        let args = test_args(&[OsStr::new("--input-trie"), trie_file.as_os_str()]);
+        let gossiper = Gossiper::with_transport(&args, InMemoryPortalNode::new());
+        std::fs::remove_file(&trie_file)?;
+        let gossiper = gossiper?;
+        assert_eq!(gossiper.slot, 1);
+        assert_eq!(gossiper.evm.block(), 1);
+        assert_eq!(gossiper.evm.block_hash(), Some(slot_report.block_hash));
+        Ok(())
+    }
+
+    #[test]
+    fn sample_rate() {
+        assert_eq!(parse_sample_rate("0").unwrap(), 0.0);
+        assert_eq!(parse_sample_rate("0.25").unwrap(), 0.25);
+        assert_eq!(parse_sample_rate("1").unwrap(), 1.0);
+        assert!(parse_sample_rate("1.5").is_err());
+        assert!(parse_sample_rate("-0.1").is_err());
+        assert!(parse_sample_rate("all").is_err());
+    }
+
+    #[tokio::test]
+    async fn sampling_is_deterministic_with_seed() -> anyhow::Result<()> {
+        let args = test_args(&[
+            "--slots",
+            "0",
+            "--verify-sample-rate",
+            "0.1",
+            "--seed",
+            "42",
+        ]);
+        let mut gossiper = Gossiper::with_transport(&args, InMemoryPortalNode::new())?;
+        let gossiped = gossiper.gossip_genesis().await?.gossip.gossiped;
+        let other_gossiper = Gossiper::with_transport(&args, InMemoryPortalNode::new())?;
+
+        let sample = gossiper.sample_gossiped_content(&gossiped);
+        let other_sample = other_gossiper.sample_gossiped_content(&gossiped);
+        assert_eq!(sample.len(), (gossiped.len() as f64 / 10.0).ceil() as usize);
+        assert_eq!(
+            sample.iter().map(|content| &content.key).collect_vec(),
+            other_sample
+                .iter()
+                .map(|content| &content.key)
+                .collect_vec()
+        );
+        Ok(())
+    }
+
+    /// Transport that fails every request.
+    struct FailingTransport;
+
+    impl PortalTransport for FailingTransport {
+        async fn gossip(
+            &self,
+            _key: VerkleContentKey,
+            _value: VerkleContentValue,
+        ) -> anyhow::Result<u32> {
+            bail!("Portal node is down")
+        }
+
+        async fn recursive_find_content(
+            &self,
+            _key: VerkleContentKey,
+        ) -> anyhow::Result<ContentInfo> {
+            bail!("Portal node is down")
+        }
+    }
+
+    #[tokio::test]
+    async fn continue_on_error() -> anyhow::Result<()> {
+        let args = test_args(&["--slots", "1"]);
+        let mut gossiper = Gossiper::with_transport(&args, FailingTransport)?;
+        assert!(gossiper
+            .gossip_slot(1, Some(read_beacon_block(1)?))
+            .await
+            .is_err());
+
+        let args = test_args(&["--slots", "1", "--continue-on-error"]);
+        let mut gossiper = Gossiper::with_transport(&args, FailingTransport)?;
+        let slot_report = gossiper.gossip_slot(1, Some(read_beacon_block(1)?)).await?;
+        assert!(slot_report.is_some_and(|slot_report| slot_report.gossip.gossiped.is_empty()));
+        assert_eq!(gossiper.slot, 1);
+        assert_eq!(
+            gossiper
+                .failed_slots
+                .iter()
+                .map(|(slot, _)| *slot)
+                .collect_vec(),
+            vec![1]
+        );
+        Ok(())
+    }
+
+    /// Transport that delays every request.
+    struct SlowTransport {
+        portal_node: InMemoryPortalNode,
+        delay: Duration,
+    }
+
+    impl PortalTransport for SlowTransport {
+        async fn gossip(
+            &self,
+            key: VerkleContentKey,
+            value: VerkleContentValue,
+        ) -> anyhow::Result<u32> {
+            tokio::time::sleep(self.delay).await;
+            self.portal_node.gossip(key, value).await
+        }
+
+        async fn recursive_find_content(
+            &self,
+            key: VerkleContentKey,
+        ) -> anyhow::Result<ContentInfo> {
+            tokio::time::sleep(self.delay).await;
+            self.portal_node.recursive_find_content(key).await
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_slot_lags() -> anyhow::Result<()> {
+        let args = test_args(&["--slots", "1", "--slot-time-warning-secs", "0"]);
+        let transport = SlowTransport {
+            portal_node: InMemoryPortalNode::new(),
+            delay: Duration::from_millis(10),
+        };
+        let mut gossiper = Gossiper::with_transport(&args, transport)?;
+
+        let slot_report = gossiper.gossip_slot(1, Some(read_beacon_block(1)?)).await?;
+
+        assert!(slot_report.is_some());
+        assert!(gossiper.lag >= Duration::from_millis(10));
+        Ok(())
+    }
+
+    #[test]
+    fn json_output() -> anyhow::Result<()> {
+        let slot_report = SlotReport {
+            block_number: 1,
+            block_hash: B256::repeat_byte(1),
+            state_root: B256::repeat_byte(2),
+            gossip: GossipReport {
+                gossiped: vec![],
+                skipped: 3,
+                elapsed: Duration::from_millis(4),
+            },
+        };
+        let required_fields = [
+            "type",
+            "slot",
+            "block_number",
+            "block_hash",
+            "state_root",
+            "gossiped",
+            "gossiped_bytes",
+            "skipped",
+            "elapsed_ms",
+        ];
+
+        let value = serde_json::to_value(JsonOutput::slot(1, Some(&slot_report)))?;
+        for field in required_fields {
+            assert!(value.get(field).is_some(), "Missing field: {field}");
+        }
+        assert_eq!(value["type"], "slot");
+        assert_eq!(value["block_number"], 1);
+        assert_eq!(
+            value["block_hash"],
+            serde_json::to_value(B256::repeat_byte(1))?
+        );
+        assert_eq!(value["skipped"], 3);
+        assert_eq!(value["elapsed_ms"], 4);
+
+        let value = serde_json::to_value(JsonOutput::slot(2, None))?;
+        for field in required_fields {
+            assert!(value.get(field).is_some(), "Missing field: {field}");
+        }
+        assert!(value["block_hash"].is_null());
+
+        let value = serde_json::to_value(JsonOutput::summary(
+            2,
+            &GossipSummary::default(),
+            Duration::ZERO,
+            Duration::ZERO,
+            vec![],
+        ))?;
+        assert_eq!(value["type"], "summary");
+        assert_eq!(value["last_slot"], 2);
+        Ok(())
+    }
+}
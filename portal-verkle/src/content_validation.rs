@@ -0,0 +1,192 @@
+use ethportal_api::{ContentValue, OverlayContentKey, VerkleContentKey, VerkleContentValue};
+use portal_verkle_primitives::{constants::PORTAL_NETWORK_NODE_WIDTH, portal::PortalVerkleNode};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ContentValidationError {
+    #[error("Content value doesn't match content key! key: {key}, value: {value}")]
+    KeyValueMismatch { key: String, value: String },
+    #[error("Invalid fragment index: {0}")]
+    InvalidFragmentIndex(u8),
+    #[error("Content value failed verification: {0}")]
+    VerificationFailed(#[source] anyhow::Error),
+}
+
+/// Validation of the content value, independent of how it was obtained.
+pub trait ValidateContent {
+    /// Validates that the content value is of the type that matches the content key, and that it
+    /// verifies against the commitment from the content key.
+    ///
+    /// The fragment index of fragment nodes has to be smaller than [PORTAL_NETWORK_NODE_WIDTH],
+    /// as it's used to calculate the indices of their children.
+    fn validate_against_key(&self, key: &VerkleContentKey) -> Result<(), ContentValidationError>;
+
+    /// Validates only the structure of the content value: that its type matches the content key
+    /// and that the fragment index of fragment nodes is valid.
+    ///
+    /// It doesn't verify the content value against the commitment from the content key, so it
+    /// should only be used for content from a trusted source.
+    fn validate_structure_against_key(
+        &self,
+        key: &VerkleContentKey,
+    ) -> Result<(), ContentValidationError>;
+}
+
+impl ValidateContent for VerkleContentValue {
+    fn validate_against_key(&self, key: &VerkleContentKey) -> Result<(), ContentValidationError> {
+        validate(self, key, /* verify= */ true)
+    }
+
+    fn validate_structure_against_key(
+        &self,
+        key: &VerkleContentKey,
+    ) -> Result<(), ContentValidationError> {
+        validate(self, key, /* verify= */ false)
+    }
+}
+
+fn validate(
+    value: &VerkleContentValue,
+    key: &VerkleContentKey,
+    verify: bool,
+) -> Result<(), ContentValidationError> {
+    let result = match (key, value) {
+        (
+            VerkleContentKey::Bundle(commitment),
+            VerkleContentValue::Node(PortalVerkleNode::BranchBundle(node)),
+        ) if verify => node.verify(commitment),
+        (
+            VerkleContentKey::Bundle(commitment),
+            VerkleContentValue::Node(PortalVerkleNode::LeafBundle(node)),
+        ) if verify => node.verify(commitment),
+        (
+            VerkleContentKey::Bundle(_),
+            VerkleContentValue::Node(
+                PortalVerkleNode::BranchBundle(_) | PortalVerkleNode::LeafBundle(_),
+            ),
+        ) => Ok(()),
+        (
+            VerkleContentKey::BranchFragment(commitment),
+            VerkleContentValue::Node(PortalVerkleNode::BranchFragment(node)),
+        ) => {
+            validate_fragment_index(node.fragment_index())?;
+            if verify {
+                node.verify(commitment)
+            } else {
+                Ok(())
+            }
+        }
+        (
+            VerkleContentKey::LeafFragment(leaf_fragment_key),
+            VerkleContentValue::Node(PortalVerkleNode::LeafFragment(node)),
+        ) => {
+            validate_fragment_index(node.fragment_index())?;
+            if verify {
+                node.verify(&leaf_fragment_key.commitment)
+            } else {
+                Ok(())
+            }
+        }
+        _ => {
+            return Err(ContentValidationError::KeyValueMismatch {
+                key: key.to_hex(),
+                value: value.to_hex(),
+            })
+        }
+    };
+    result.map_err(|err| ContentValidationError::VerificationFailed(err.into()))
+}
+
+fn validate_fragment_index(fragment_index: u8) -> Result<(), ContentValidationError> {
+    if fragment_index as usize >= PORTAL_NETWORK_NODE_WIDTH {
+        return Err(ContentValidationError::InvalidFragmentIndex(fragment_index));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{in_memory_portal_node::InMemoryPortalNode, utils::read_genesis_for_test};
+
+    /// Returns the keys and values of the genesis state trie.
+    async fn genesis_content() -> anyhow::Result<Vec<(VerkleContentKey, VerkleContentValue)>> {
+        let portal_node = InMemoryPortalNode::new();
+        let (_, keys) = portal_node
+            .gossip_trie(&read_genesis_for_test()?.into_state_writes())
+            .await?;
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                let value = portal_node
+                    .get(&key)
+                    .expect("gossiped content should be stored");
+                (key, value)
+            })
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn matching_key_and_value() -> anyhow::Result<()> {
+        for (key, value) in genesis_content().await? {
+            value.validate_against_key(&key)?;
+            value.validate_structure_against_key(&key)?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bundle_key_with_fragment_value() -> anyhow::Result<()> {
+        let content = genesis_content().await?;
+        let (bundle_key, _) = content
+            .iter()
+            .find(|(key, _)| matches!(key, VerkleContentKey::Bundle(_)))
+            .expect("to find bundle");
+        let (_, fragment_value) = content
+            .iter()
+            .find(|(key, _)| matches!(key, VerkleContentKey::BranchFragment(_)))
+            .expect("to find branch fragment");
+
+        assert!(matches!(
+            fragment_value.validate_against_key(bundle_key),
+            Err(ContentValidationError::KeyValueMismatch { .. })
+        ));
+        assert!(matches!(
+            fragment_value.validate_structure_against_key(bundle_key),
+            Err(ContentValidationError::KeyValueMismatch { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wrong_commitment() -> anyhow::Result<()> {
+        let content = genesis_content().await?;
+        let mut bundles = content
+            .iter()
+            .filter(|(key, _)| matches!(key, VerkleContentKey::Bundle(_)));
+        let (key, _) = bundles.next().expect("to find bundle");
+        let (_, other_value) = bundles.next().expect("to find other bundle");
+
+        assert!(matches!(
+            other_value.validate_against_key(key),
+            Err(ContentValidationError::VerificationFailed(_))
+        ));
+        // The structure is still valid
+        other_value.validate_structure_against_key(key)?;
+        Ok(())
+    }
+
+    #[test]
+    fn fragment_index() {
+        assert!(validate_fragment_index(0).is_ok());
+        assert!(validate_fragment_index(PORTAL_NETWORK_NODE_WIDTH as u8 - 1).is_ok());
+        assert!(matches!(
+            validate_fragment_index(PORTAL_NETWORK_NODE_WIDTH as u8),
+            Err(ContentValidationError::InvalidFragmentIndex(16))
+        ));
+        assert!(matches!(
+            validate_fragment_index(u8::MAX),
+            Err(ContentValidationError::InvalidFragmentIndex(u8::MAX))
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+use ethportal_api::VerkleContentValue;
+use portal_verkle_primitives::portal::{
+    BranchBundleNode, BranchFragmentNode, LeafBundleNode, LeafFragmentNode, PortalVerkleNode,
+    PortalVerkleNodeWithProof,
+};
+
+/// Accessors of the node that is stored in the content value.
+///
+/// They only match the content value without the proof (as returned by content lookups), and
+/// return `None` for any other content value.
+pub trait VerkleContentValueExt {
+    fn as_node(&self) -> Option<&PortalVerkleNode>;
+
+    fn as_branch_bundle(&self) -> Option<&BranchBundleNode> {
+        match self.as_node()? {
+            PortalVerkleNode::BranchBundle(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    fn as_branch_fragment(&self) -> Option<&BranchFragmentNode> {
+        match self.as_node()? {
+            PortalVerkleNode::BranchFragment(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    fn as_leaf_bundle(&self) -> Option<&LeafBundleNode> {
+        match self.as_node()? {
+            PortalVerkleNode::LeafBundle(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    fn as_leaf_fragment(&self) -> Option<&LeafFragmentNode> {
+        match self.as_node()? {
+            PortalVerkleNode::LeafFragment(node) => Some(node),
+            _ => None,
+        }
+    }
+}
+
+impl VerkleContentValueExt for VerkleContentValue {
+    fn as_node(&self) -> Option<&PortalVerkleNode> {
+        match self {
+            VerkleContentValue::Node(node) => Some(node),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the content value without the proof, the way it's stored by Portal Network nodes.
+pub fn strip_proof(value: VerkleContentValue) -> VerkleContentValue {
+    match value {
+        VerkleContentValue::NodeWithProof(node_with_proof) => {
+            VerkleContentValue::Node(match node_with_proof {
+                PortalVerkleNodeWithProof::BranchBundle(node) => {
+                    PortalVerkleNode::BranchBundle(node.node)
+                }
+                PortalVerkleNodeWithProof::BranchFragment(node) => {
+                    PortalVerkleNode::BranchFragment(node.node)
+                }
+                PortalVerkleNodeWithProof::LeafBundle(node) => {
+                    PortalVerkleNode::LeafBundle(node.node)
+                }
+                PortalVerkleNodeWithProof::LeafFragment(node) => {
+                    PortalVerkleNode::LeafFragment(node.node)
+                }
+            })
+        }
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+    use ethportal_api::{OverlayContentKey, VerkleContentKey};
+    use portal_verkle_primitives::verkle::{
+        nodes::portal_leaf_node_builder::PortalLeafNodeBuilder, VerkleTrie,
+    };
+
+    use super::*;
+    use crate::{in_memory_portal_node::InMemoryPortalNode, utils::read_genesis_for_test};
+
+    #[tokio::test]
+    async fn accessors() -> anyhow::Result<()> {
+        let portal_node = InMemoryPortalNode::new();
+        let (_, keys) = portal_node
+            .gossip_trie(&read_genesis_for_test()?.into_state_writes())
+            .await?;
+
+        let mut branch_bundles = 0;
+        let mut leaf_bundles = 0;
+        for key in keys {
+            let value = portal_node
+                .get(&key)
+                .expect("gossiped content should be stored");
+            let accessors = (
+                value.as_branch_bundle().is_some(),
+                value.as_branch_fragment().is_some(),
+                value.as_leaf_bundle().is_some(),
+                value.as_leaf_fragment().is_some(),
+            );
+            match key {
+                VerkleContentKey::Bundle(_) if accessors == (true, false, false, false) => {
+                    branch_bundles += 1
+                }
+                VerkleContentKey::Bundle(_) if accessors == (false, false, true, false) => {
+                    leaf_bundles += 1
+                }
+                VerkleContentKey::BranchFragment(_) => {
+                    assert_eq!(accessors, (false, true, false, false))
+                }
+                VerkleContentKey::LeafFragment(_) => {
+                    assert_eq!(accessors, (false, false, false, true))
+                }
+                _ => panic!(
+                    "Unexpected accessors {accessors:?} for key: {}",
+                    key.to_hex()
+                ),
+            }
+        }
+        assert!(branch_bundles > 0);
+        assert!(leaf_bundles > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn accessors_with_proof() -> anyhow::Result<()> {
+        let state_writes = read_genesis_for_test()?.into_state_writes();
+        let mut trie = VerkleTrie::new();
+        trie.update(&state_writes);
+        let stem_state_write = state_writes.iter().next().expect("to find stem");
+        let path_to_leaf = trie.traverse_to_leaf(&stem_state_write.stem)?;
+
+        let value = VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::LeafBundle(
+            PortalLeafNodeBuilder::new(&path_to_leaf).bundle_node_with_proof(B256::ZERO),
+        ));
+        assert!(value.as_node().is_none());
+        assert!(value.as_branch_bundle().is_none());
+        assert!(value.as_branch_fragment().is_none());
+        assert!(value.as_leaf_bundle().is_none());
+        assert!(value.as_leaf_fragment().is_none());
+
+        let value = strip_proof(value);
+        assert!(value.as_leaf_bundle().is_some());
+        Ok(())
+    }
+}
@@ -1,13 +1,40 @@
-use alloy_primitives::B256;
-use portal_verkle_primitives::verkle::error::VerkleTrieError;
+use alloy_primitives::{Address, B256};
+use portal_verkle_primitives::{verkle::error::VerkleTrieError, Stem};
 use thiserror::Error;
 
+use crate::types::witness::WitnessError;
+
 #[derive(Debug, Error)]
 pub enum EvmError {
     #[error("Expected block {expected}, but received {actual}")]
     UnexpectedBlock { expected: u64, actual: u64 },
+    #[error("Expected block with parent {expected}, but received parent {actual}")]
+    UnexpectedParent { expected: B256, actual: B256 },
     #[error("Wrong state root. Expected {expected}, but actual {actual}")]
     WrongStateRoot { expected: B256, actual: B256 },
+    /// The state root doesn't match after processing the block, with state root diagnostics
+    /// enabled (otherwise, [EvmError::WrongStateRoot] is returned).
+    ///
+    /// The `diverged_stems` are the (first few) stems whose values in the state trie, before the
+    /// block was processed, didn't match the current values from the execution witness.
+    #[error(
+        "Wrong state root after block {block}. Expected {expected}, but actual {actual} \
+        ({written_stems} stems written, diverged stems: {diverged_stems:?})"
+    )]
+    WrongStateRootAfterBlock {
+        block: u64,
+        expected: B256,
+        actual: B256,
+        written_stems: usize,
+        diverged_stems: Vec<Stem>,
+    },
+    /// The current values of the execution witness don't match the state trie.
+    #[error("Execution witness doesn't match the state (diverged stems: {diverged_stems:?})")]
+    WitnessPreStateMismatch { diverged_stems: Vec<Stem> },
+    #[error("Balance overflow while crediting withdrawals to {address}")]
+    WithdrawalBalanceOverflow { address: Address },
+    #[error("Invalid execution witness: {0}")]
+    InvalidWitness(#[from] WitnessError),
     #[error("Trie error: {0}")]
-    TrieError(VerkleTrieError),
+    TrieError(#[from] VerkleTrieError),
 }
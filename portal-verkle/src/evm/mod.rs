@@ -1,4 +1,4 @@
 pub mod error;
 mod verkle_evm;
 
-pub use verkle_evm::VerkleEvm;
+pub use verkle_evm::{BlockStats, ProcessBlockResult, VerkleEvm};
@@ -1,29 +1,76 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
-use alloy_primitives::{address, keccak256, U8};
+use alloy_primitives::{address, keccak256, Address, B256, U256, U8};
 use portal_verkle_primitives::{
     constants::{BALANCE_LEAF_KEY, CODE_KECCAK_LEAF_KEY, NONCE_LEAF_KEY, VERSION_LEAF_KEY},
     ssz::TriePath,
     verkle::{
         genesis_config::GenesisConfig, storage::AccountStorageLayout, StateWrites, VerkleTrie,
     },
-    TrieValue,
+    Stem, TrieKey, TrieValue,
 };
 
 use super::error::EvmError;
 use crate::types::{
-    beacon::ExecutionPayload,
-    witness::{StateDiff, StemStateDiff, SuffixStateDiff},
+    beacon::{ExecutionPayload, Withdrawal},
+    witness::{ExecutionWitness, StateDiff, StemStateDiff, SuffixStateDiff, WitnessError},
 };
 
+const GWEI_TO_WEI: U256 = U256::from_limbs([1_000_000_000, 0, 0, 0]);
+
+/// The maximum number of diverged stems that are reported when state root doesn't match.
+const MAX_REPORTED_DIVERGED_STEMS: usize = 10;
+
 pub struct VerkleEvm {
     block: u64,
+    /// The hash of the last processed block, if known.
+    block_hash: Option<B256>,
     state_trie: VerkleTrie,
+    state_root_diagnostics: bool,
 }
 
 pub struct ProcessBlockResult {
     pub state_writes: StateWrites,
     pub new_branch_nodes: HashSet<TriePath>,
+    pub stats: BlockStats,
+}
+
+/// The statistics of the state changes of a block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockStats {
+    /// The number of stems with at least one write.
+    pub stems: usize,
+    /// The number of written values.
+    pub suffix_writes: usize,
+    /// The number of written values that weren't present before the block.
+    pub new_values: usize,
+    /// The number of written values that were present before the block.
+    pub updated_values: usize,
+}
+
+impl BlockStats {
+    pub fn from_state_diff(state_diff: &StateDiff) -> Self {
+        let mut stats = Self::default();
+        for stem_state_diff in state_diff {
+            let mut has_writes = false;
+            for suffix_diff in &stem_state_diff.suffix_diffs {
+                if suffix_diff.new_value.is_none() {
+                    continue;
+                }
+                has_writes = true;
+                stats.suffix_writes += 1;
+                if suffix_diff.current_value.is_some() {
+                    stats.updated_values += 1;
+                } else {
+                    stats.new_values += 1;
+                }
+            }
+            if has_writes {
+                stats.stems += 1;
+            }
+        }
+        stats
+    }
 }
 
 impl VerkleEvm {
@@ -32,7 +79,31 @@ impl VerkleEvm {
         state_trie.update(&genesis_config.into_state_writes());
         Ok(Self {
             block: 0,
+            block_hash: None,
             state_trie,
+            state_root_diagnostics: false,
+        })
+    }
+
+    /// Creates the EVM that continues processing blocks from the given state.
+    ///
+    /// Returns error if state root of the trie doesn't match the provided `state_root`.
+    pub fn new_from_trie(
+        state_trie: VerkleTrie,
+        block_number: u64,
+        state_root: B256,
+    ) -> Result<Self, EvmError> {
+        if state_trie.root() != state_root {
+            return Err(EvmError::WrongStateRoot {
+                expected: state_root,
+                actual: state_trie.root(),
+            });
+        }
+        Ok(Self {
+            block: block_number,
+            block_hash: None,
+            state_trie,
+            state_root_diagnostics: false,
         })
     }
 
@@ -40,10 +111,48 @@ impl VerkleEvm {
         &self.state_trie
     }
 
+    /// Mutable access to the state trie, e.g. to seed the state before processing a block in
+    /// tests.
+    ///
+    /// Any manual change of the trie changes its root, so it will no longer match the state root
+    /// of the last processed block. Processing the next block fails with
+    /// [EvmError::WrongStateRoot], unless the changes are also part of that block's
+    /// pre-state.
+    pub fn state_trie_mut(&mut self) -> &mut VerkleTrie {
+        &mut self.state_trie
+    }
+
     pub fn block(&self) -> u64 {
         self.block
     }
 
+    /// Returns the hash of the last processed block, if known.
+    pub fn block_hash(&self) -> Option<B256> {
+        self.block_hash
+    }
+
+    /// Sets the hash of the last processed block (e.g. genesis block hash).
+    ///
+    /// If set, the parent hash of the next processed block has to match it. Otherwise, the parent
+    /// hash of the first processed block is not checked.
+    pub fn set_block_hash(&mut self, block_hash: B256) {
+        self.block_hash = Some(block_hash);
+    }
+
+    /// Whether to compare the state trie with the execution witness before processing each block.
+    ///
+    /// If enabled and state root doesn't match after processing the block, the returned error
+    /// contains the stems whose values didn't match the execution witness. This requires a trie
+    /// lookup for every value in the witness, so it's disabled by default.
+    pub fn set_state_root_diagnostics(&mut self, enabled: bool) {
+        self.state_root_diagnostics = enabled;
+    }
+
+    /// Processes the block and returns its state changes.
+    ///
+    /// If the state root doesn't match after the block, the block number and hash of the EVM are
+    /// not updated, but the state trie already contains the changes of the block. The EVM can't
+    /// process any more blocks in that case.
     pub fn process_block(
         &mut self,
         execution_payload: &ExecutionPayload,
@@ -54,36 +163,117 @@ impl VerkleEvm {
                 actual: execution_payload.block_number.to(),
             });
         }
+        if let Some(block_hash) = self.block_hash {
+            if block_hash != execution_payload.parent_hash {
+                return Err(EvmError::UnexpectedParent {
+                    expected: block_hash,
+                    actual: execution_payload.parent_hash,
+                });
+            }
+        }
 
+        execution_payload.execution_witness.validate()?;
         let mut state_diff = execution_payload.execution_witness.state_diff.clone();
 
         if self.block == 0 {
             update_state_diff_for_eip2935(&mut state_diff);
         }
+        update_state_diff_for_withdrawals(
+            &self.state_trie,
+            &mut state_diff,
+            &execution_payload.withdrawals,
+        )?;
 
-        let state_writes = StateWrites::new(
-            state_diff
-                .into_iter()
-                .filter_map(StemStateDiff::into_stem_state_write)
-                .collect(),
-        );
+        let diverged_stems = if self.state_root_diagnostics {
+            find_diverged_stems(&self.state_trie, &state_diff)
+        } else {
+            vec![]
+        };
 
-        let new_branch_nodes = self.state_trie.update(&state_writes);
-        self.block += 1;
+        let result = self.apply_state_diff(state_diff)?;
 
         if self.state_trie.root() != execution_payload.state_root {
-            return Err(EvmError::WrongStateRoot {
+            if !self.state_root_diagnostics {
+                return Err(EvmError::WrongStateRoot {
+                    expected: execution_payload.state_root,
+                    actual: self.state_trie.root(),
+                });
+            }
+            return Err(EvmError::WrongStateRootAfterBlock {
+                block: self.block + 1,
                 expected: execution_payload.state_root,
                 actual: self.state_trie.root(),
+                written_stems: result.state_writes.iter().count(),
+                diverged_stems,
             });
         }
+        self.block += 1;
+        self.block_hash = Some(execution_payload.block_hash);
+        Ok(result)
+    }
+
+    /// Applies the state diff of the execution witness to the state trie, without the rest of
+    /// the block (e.g. for the witness obtained from another client).
+    ///
+    /// The proof of the witness is not verified, so the caller has to verify it before trusting
+    /// the result. The current values of the state diff have to match the state trie, otherwise
+    /// [EvmError::WitnessPreStateMismatch] is returned.
+    /// The block number and hash of the EVM are not updated.
+    pub fn apply_witness(
+        &mut self,
+        execution_witness: &ExecutionWitness,
+    ) -> Result<ProcessBlockResult, EvmError> {
+        execution_witness.validate()?;
+        let diverged_stems = find_diverged_stems(&self.state_trie, &execution_witness.state_diff);
+        if !diverged_stems.is_empty() {
+            return Err(EvmError::WitnessPreStateMismatch { diverged_stems });
+        }
+        self.apply_state_diff(execution_witness.state_diff.clone())
+    }
+
+    /// Applies the state diff to the state trie.
+    ///
+    /// Stems without writes are skipped. Any other invalid stem state diff is returned as error,
+    /// as the state diff might be updated after the witness was validated (e.g. for withdrawals).
+    fn apply_state_diff(&mut self, state_diff: StateDiff) -> Result<ProcessBlockResult, EvmError> {
+        let stats = BlockStats::from_state_diff(&state_diff);
+        let mut stem_state_writes = vec![];
+        for stem_state_diff in state_diff {
+            match stem_state_diff.try_into_stem_state_write() {
+                Ok(stem_state_write) => stem_state_writes.push(stem_state_write),
+                Err(WitnessError::NoWrites { .. }) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let state_writes = StateWrites::new(stem_state_writes);
+        let new_branch_nodes = self.state_trie.update(&state_writes);
         Ok(ProcessBlockResult {
             state_writes,
             new_branch_nodes,
+            stats,
         })
     }
 }
 
+/// Returns the stems (up to [MAX_REPORTED_DIVERGED_STEMS]) whose values in the state trie don't
+/// match the current values of the state diff.
+fn find_diverged_stems(state_trie: &VerkleTrie, state_diff: &StateDiff) -> Vec<Stem> {
+    state_diff
+        .iter()
+        .filter(|stem_state_diff| {
+            stem_state_diff.suffix_diffs.iter().any(|suffix_diff| {
+                let key = TrieKey::from_stem_and_last_byte(
+                    &stem_state_diff.stem,
+                    suffix_diff.suffix.byte(0),
+                );
+                state_trie.get(&key).copied() != suffix_diff.current_value
+            })
+        })
+        .map(|stem_state_diff| stem_state_diff.stem)
+        .take(MAX_REPORTED_DIVERGED_STEMS)
+        .collect()
+}
+
 /// Eip-2935: Initialize account: "0xfffffffffffffffffffffffffffffffffffffffe"
 /// NOTE: This is not included into execution_witness (probably a bug).
 fn update_state_diff_for_eip2935(state_diff: &mut StateDiff) {
@@ -110,6 +300,108 @@ fn update_state_diff_for_eip2935(state_diff: &mut StateDiff) {
     }
 }
 
+/// Credits withdrawals to the balances of their addresses.
+///
+/// Balances that are already updated by the execution witness are left as they are, as they
+/// already include the withdrawals.
+fn update_state_diff_for_withdrawals(
+    state_trie: &VerkleTrie,
+    state_diff: &mut StateDiff,
+    withdrawals: &[Withdrawal],
+) -> Result<(), EvmError> {
+    let mut credits = BTreeMap::<Address, U256>::new();
+    for withdrawal in withdrawals {
+        let amount = U256::from(withdrawal.amount.to::<u64>()) * GWEI_TO_WEI;
+        let credit = credits.entry(withdrawal.address).or_default();
+        *credit = credit
+            .checked_add(amount)
+            .ok_or(EvmError::WithdrawalBalanceOverflow {
+                address: withdrawal.address,
+            })?;
+    }
+
+    for (address, credit) in credits {
+        let storage_layout = AccountStorageLayout::new(address);
+        let stem = storage_layout.account_storage_stem();
+        let balance = state_trie.get(&storage_layout.balance_key()).copied();
+
+        // Zero credit doesn't create the non-existing account
+        if credit.is_zero() && balance.is_none() {
+            continue;
+        }
+
+        // The state diff has to stay sorted by stem (and suffix), see [ExecutionWitness::validate]
+        let stem_index =
+            match state_diff.binary_search_by_key(stem, |stem_state_diff| stem_state_diff.stem) {
+                Ok(index) => index,
+                Err(index) => {
+                    state_diff.insert(
+                        index,
+                        StemStateDiff {
+                            stem: *stem,
+                            suffix_diffs: vec![],
+                        },
+                    );
+                    index
+                }
+            };
+        let suffix_diffs = &mut state_diff[stem_index].suffix_diffs;
+
+        let balance_suffix_diff =
+            get_or_insert_suffix_diff(suffix_diffs, BALANCE_LEAF_KEY, balance);
+        if balance_suffix_diff.new_value.is_some() {
+            continue;
+        }
+        balance_suffix_diff.new_value = Some(TrieValue::from(
+            U256::from(balance.unwrap_or(TrieValue::ZERO))
+                .checked_add(credit)
+                .ok_or(EvmError::WithdrawalBalanceOverflow { address })?,
+        ));
+
+        // Withdrawal to the non-existing account creates it. The block might have already read
+        // (or written) its header, in which case the existing suffix diff is updated.
+        if balance.is_none() {
+            let suffix_writes = [
+                (VERSION_LEAF_KEY, TrieValue::ZERO),
+                (NONCE_LEAF_KEY, TrieValue::ZERO),
+                (CODE_KECCAK_LEAF_KEY, TrieValue::from(keccak256([]))),
+            ];
+            for (suffix, trie_value) in suffix_writes {
+                get_or_insert_suffix_diff(suffix_diffs, suffix, /* current_value= */ None)
+                    .new_value
+                    .get_or_insert(trie_value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the diff of the given suffix. If it isn't present, the diff with the current value
+/// (and without the new value) is inserted at its sorted position.
+fn get_or_insert_suffix_diff(
+    suffix_diffs: &mut Vec<SuffixStateDiff>,
+    suffix: u8,
+    current_value: Option<TrieValue>,
+) -> &mut SuffixStateDiff {
+    let index = match suffix_diffs
+        .binary_search_by_key(&suffix, |suffix_diff| suffix_diff.suffix.byte(0))
+    {
+        Ok(index) => index,
+        Err(index) => {
+            suffix_diffs.insert(
+                index,
+                SuffixStateDiff {
+                    suffix: U8::from(suffix),
+                    current_value,
+                    new_value: None,
+                },
+            );
+            index
+        }
+    };
+    &mut suffix_diffs[index]
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -117,25 +409,172 @@ mod tests {
         io::{stdout, BufReader},
     };
 
-    use alloy_primitives::{b256, B256};
+    use alloy_primitives::U64;
     use anyhow::{bail, Result};
-    use portal_verkle_primitives::verkle::trie_printer::TriePrinter;
+    use portal_verkle_primitives::verkle::{trie_printer::TriePrinter, StemStateWrite};
 
     use crate::{
         types::SuccessMessage,
-        utils::{beacon_slot_path, read_genesis_for_test, test_path},
+        utils::{beacon_slot_path, read_genesis_for_test, test_path, GENESIS_STATE_ROOT},
     };
 
     use super::*;
 
+    fn withdrawal(index: u64, address: Address, amount: u64) -> Withdrawal {
+        Withdrawal {
+            index: U64::from(index),
+            validator_index: U64::from(index),
+            address,
+            amount: U64::from(amount),
+        }
+    }
+
     #[test]
     fn genesis() -> Result<()> {
-        const STATE_ROOT: B256 =
-            b256!("1fbf85345a3cbba9a6d44f991b721e55620a22397c2a93ee8d5011136ac300ee");
-
         let evm = VerkleEvm::new(read_genesis_for_test()?)?;
 
-        assert_eq!(evm.state_trie.root(), STATE_ROOT);
+        assert_eq!(evm.state_trie.root(), GENESIS_STATE_ROOT);
+        Ok(())
+    }
+
+    #[test]
+    fn state_trie_mut() -> Result<()> {
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        let storage_layout = AccountStorageLayout::new(Address::repeat_byte(0x42));
+        let balance = TrieValue::from(U256::from(1_000));
+
+        evm.state_trie_mut()
+            .update(&StateWrites::new(vec![StemStateWrite {
+                stem: *storage_layout.account_storage_stem(),
+                writes: [(BALANCE_LEAF_KEY, balance)].into_iter().collect(),
+            }]));
+
+        assert_eq!(
+            evm.state_trie().get(&storage_layout.balance_key()),
+            Some(&balance)
+        );
+        assert_ne!(evm.state_trie().root(), GENESIS_STATE_ROOT);
+        Ok(())
+    }
+
+    #[test]
+    fn block_stats() {
+        let suffix_diff =
+            |suffix: u8, current_value: Option<u64>, new_value: Option<u64>| SuffixStateDiff {
+                suffix: U8::from(suffix),
+                current_value: current_value.map(|value| TrieValue::from(U256::from(value))),
+                new_value: new_value.map(|value| TrieValue::from(U256::from(value))),
+            };
+        let state_diff = vec![
+            StemStateDiff {
+                stem: *AccountStorageLayout::new(Address::repeat_byte(1)).account_storage_stem(),
+                suffix_diffs: vec![
+                    suffix_diff(BALANCE_LEAF_KEY, Some(1), Some(2)),
+                    suffix_diff(NONCE_LEAF_KEY, Some(0), Some(1)),
+                    suffix_diff(CODE_KECCAK_LEAF_KEY, Some(0), None),
+                ],
+            },
+            StemStateDiff {
+                stem: *AccountStorageLayout::new(Address::repeat_byte(2)).account_storage_stem(),
+                suffix_diffs: vec![
+                    suffix_diff(VERSION_LEAF_KEY, None, Some(0)),
+                    suffix_diff(BALANCE_LEAF_KEY, None, Some(3)),
+                ],
+            },
+            // Only read
+            StemStateDiff {
+                stem: *AccountStorageLayout::new(Address::repeat_byte(3)).account_storage_stem(),
+                suffix_diffs: vec![suffix_diff(BALANCE_LEAF_KEY, Some(4), None)],
+            },
+        ];
+
+        assert_eq!(
+            BlockStats::from_state_diff(&state_diff),
+            BlockStats {
+                stems: 2,
+                suffix_writes: 4,
+                new_values: 2,
+                updated_values: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn apply_witness() -> Result<()> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let execution_payload = response.data.message.body.execution_payload;
+
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        let result = evm.apply_witness(&execution_payload.execution_witness)?;
+        assert!(result.state_writes.iter().count() > 0);
+        assert_eq!(evm.block(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_witness_pre_state_mismatch() -> Result<()> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let execution_witness = response
+            .data
+            .message
+            .body
+            .execution_payload
+            .execution_witness;
+
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        evm.apply_witness(&execution_witness)?;
+        let state_root = evm.state_trie().root();
+
+        // The state after the witness is no longer its pre-state
+        assert!(matches!(
+            evm.apply_witness(&execution_witness),
+            Err(EvmError::WitnessPreStateMismatch { .. })
+        ));
+        assert_eq!(evm.state_trie().root(), state_root);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_state_diff_duplicate_suffix() -> Result<()> {
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        let suffix_diff = SuffixStateDiff {
+            suffix: U8::from(BALANCE_LEAF_KEY),
+            current_value: None,
+            new_value: Some(TrieValue::from(U256::from(1))),
+        };
+        let state_diff = vec![StemStateDiff {
+            stem: *AccountStorageLayout::new(Address::repeat_byte(1)).account_storage_stem(),
+            suffix_diffs: vec![suffix_diff.clone(), suffix_diff],
+        }];
+
+        assert!(matches!(
+            evm.apply_state_diff(state_diff),
+            Err(EvmError::InvalidWitness(
+                WitnessError::DuplicateSuffix { .. }
+            ))
+        ));
+        assert_eq!(evm.state_trie().root(), GENESIS_STATE_ROOT);
+        Ok(())
+    }
+
+    #[test]
+    fn new_from_trie() -> Result<()> {
+        let create_genesis_trie = || -> Result<VerkleTrie> {
+            let mut state_trie = VerkleTrie::new();
+            state_trie.update(&read_genesis_for_test()?.into_state_writes());
+            Ok(state_trie)
+        };
+
+        let evm = VerkleEvm::new_from_trie(create_genesis_trie()?, 0, GENESIS_STATE_ROOT)?;
+        assert_eq!(evm.block(), 0);
+        assert_eq!(evm.state_trie().root(), GENESIS_STATE_ROOT);
+
+        assert!(matches!(
+            VerkleEvm::new_from_trie(create_genesis_trie()?, 0, B256::ZERO),
+            Err(EvmError::WrongStateRoot { .. })
+        ));
         Ok(())
     }
 
@@ -151,6 +590,275 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn process_block_with_withdrawals() -> Result<()> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let mut execution_payload = response.data.message.body.execution_payload;
+
+        // The state after the block without withdrawals
+        let mut expected_evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        expected_evm.process_block(&execution_payload)?;
+
+        let existing_account = Address::with_last_byte(1);
+        let new_account = Address::repeat_byte(0x42);
+        execution_payload.withdrawals = vec![
+            withdrawal(0, existing_account, 1),
+            withdrawal(1, new_account, 1),
+            withdrawal(2, new_account, 2),
+        ];
+
+        // The block reads the header of the new account, before withdrawals create it
+        let new_account_layout = AccountStorageLayout::new(new_account);
+        let state_diff = &mut execution_payload.execution_witness.state_diff;
+        state_diff.push(StemStateDiff {
+            stem: *new_account_layout.account_storage_stem(),
+            suffix_diffs: [VERSION_LEAF_KEY, CODE_KECCAK_LEAF_KEY]
+                .into_iter()
+                .map(|suffix| SuffixStateDiff {
+                    suffix: U8::from(suffix),
+                    current_value: None,
+                    new_value: None,
+                })
+                .collect(),
+        });
+        state_diff.sort_by_key(|stem_state_diff| stem_state_diff.stem);
+
+        let existing_account_layout = AccountStorageLayout::new(existing_account);
+        let existing_balance = U256::from(
+            *expected_evm
+                .state_trie()
+                .get(&existing_account_layout.balance_key())
+                .expect("existing account should have balance"),
+        );
+        expected_evm.state_trie_mut().update(&StateWrites::new(vec![
+            StemStateWrite {
+                stem: *existing_account_layout.account_storage_stem(),
+                writes: [(
+                    BALANCE_LEAF_KEY,
+                    TrieValue::from(existing_balance + GWEI_TO_WEI),
+                )]
+                .into_iter()
+                .collect(),
+            },
+            StemStateWrite {
+                stem: *new_account_layout.account_storage_stem(),
+                writes: [
+                    (VERSION_LEAF_KEY, TrieValue::ZERO),
+                    (
+                        BALANCE_LEAF_KEY,
+                        TrieValue::from(U256::from(3) * GWEI_TO_WEI),
+                    ),
+                    (NONCE_LEAF_KEY, TrieValue::ZERO),
+                    (CODE_KECCAK_LEAF_KEY, TrieValue::from(keccak256([]))),
+                ]
+                .into_iter()
+                .collect(),
+            },
+        ]));
+        execution_payload.state_root = expected_evm.state_trie().root();
+
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        evm.process_block(&execution_payload)?;
+        assert_eq!(evm.state_trie().root(), execution_payload.state_root);
+        Ok(())
+    }
+
+    #[test]
+    fn withdrawals_keep_state_diff_sorted() -> Result<()> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let execution_witness = response
+            .data
+            .message
+            .body
+            .execution_payload
+            .execution_witness;
+
+        let evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        let withdrawals = (0..16)
+            .map(|index| withdrawal(index, Address::repeat_byte(index as u8 * 16 + 1), 1))
+            .collect::<Vec<_>>();
+        let mut state_diff = execution_witness.state_diff.clone();
+        update_state_diff_for_withdrawals(evm.state_trie(), &mut state_diff, &withdrawals)?;
+
+        assert!(state_diff.len() > execution_witness.state_diff.len());
+        assert!(state_diff
+            .windows(2)
+            .all(|pair| pair[0].stem < pair[1].stem));
+        for stem_state_diff in &state_diff {
+            assert!(stem_state_diff
+                .suffix_diffs
+                .windows(2)
+                .all(|pair| pair[0].suffix < pair[1].suffix));
+        }
+        ExecutionWitness {
+            state_diff,
+            ..execution_witness
+        }
+        .validate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn zero_withdrawal_to_non_existing_account() -> Result<()> {
+        let evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        let existing_account = Address::with_last_byte(1);
+        let new_account = Address::repeat_byte(0x42);
+        let mut state_diff = StateDiff::new();
+        update_state_diff_for_withdrawals(
+            evm.state_trie(),
+            &mut state_diff,
+            &[
+                withdrawal(0, existing_account, 0),
+                withdrawal(1, new_account, 0),
+            ],
+        )?;
+
+        // Only the existing account is credited (with zero)
+        assert_eq!(state_diff.len(), 1);
+        assert_eq!(
+            &state_diff[0].stem,
+            AccountStorageLayout::new(existing_account).account_storage_stem()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn process_block_1_parent_hash() -> Result<()> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let execution_payload = response.data.message.body.execution_payload;
+
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        evm.set_block_hash(B256::ZERO);
+        assert!(matches!(
+            evm.process_block(&execution_payload),
+            Err(EvmError::UnexpectedParent { .. })
+        ));
+        assert_eq!(evm.block(), 0);
+
+        evm.set_block_hash(execution_payload.parent_hash);
+        evm.process_block(&execution_payload)?;
+        assert_eq!(evm.block(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn process_block_1_diverged() -> Result<()> {
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+        evm.set_state_root_diagnostics(true);
+
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let mut execution_payload = response.data.message.body.execution_payload;
+
+        // Corrupt both current and new value of one suffix
+        let stem_state_diff = execution_payload
+            .execution_witness
+            .state_diff
+            .iter_mut()
+            .find(|stem_state_diff| {
+                stem_state_diff
+                    .suffix_diffs
+                    .iter()
+                    .any(|suffix_diff| suffix_diff.current_value.is_some())
+            })
+            .expect("to find StemStateDiff with current value");
+        let diverged_stem = stem_state_diff.stem;
+        let suffix_diff = stem_state_diff
+            .suffix_diffs
+            .iter_mut()
+            .find(|suffix_diff| suffix_diff.current_value.is_some())
+            .expect("to find SuffixStateDiff with current value");
+        suffix_diff.current_value = Some(TrieValue::from(U256::from(12345)));
+        suffix_diff.new_value = Some(TrieValue::from(U256::from(12345)));
+
+        match evm.process_block(&execution_payload) {
+            Err(EvmError::WrongStateRootAfterBlock {
+                block,
+                written_stems,
+                diverged_stems,
+                ..
+            }) => {
+                assert_eq!(block, 1);
+                assert!(written_stems > 0);
+                assert_eq!(diverged_stems, vec![diverged_stem]);
+            }
+            Ok(_) => bail!("Expected error, but processing block succeeded"),
+            Err(err) => bail!("Unexpected error: {err}"),
+        }
+        assert_eq!(evm.block(), 0);
+        assert_eq!(evm.block_hash(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn process_block_1_wrong_state_root() -> Result<()> {
+        let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
+
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let mut execution_payload = response.data.message.body.execution_payload;
+        execution_payload.state_root = B256::ZERO;
+
+        assert!(matches!(
+            evm.process_block(&execution_payload),
+            Err(EvmError::WrongStateRoot { .. })
+        ));
+        assert_eq!(evm.block(), 0);
+        assert_eq!(evm.block_hash(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn withdrawals() -> Result<()> {
+        let evm = VerkleEvm::new(read_genesis_for_test()?)?;
+
+        let existing_account = Address::ZERO;
+        let new_account = address!("00000000000000000000000000000000deadbeef");
+        let withdrawals = [
+            withdrawal(0, existing_account, 2),
+            withdrawal(1, new_account, 3),
+            withdrawal(2, existing_account, 5),
+        ];
+
+        let mut state_diff = StateDiff::new();
+        update_state_diff_for_withdrawals(evm.state_trie(), &mut state_diff, &withdrawals)?;
+
+        let new_value = |address: Address, suffix: u8| {
+            let storage_layout = AccountStorageLayout::new(address);
+            state_diff
+                .iter()
+                .find(|stem_state_diff| {
+                    &stem_state_diff.stem == storage_layout.account_storage_stem()
+                })
+                .and_then(|stem_state_diff| {
+                    stem_state_diff
+                        .suffix_diffs
+                        .iter()
+                        .find(|suffix_diff| suffix_diff.suffix.byte(0) == suffix)
+                })
+                .and_then(|suffix_diff| suffix_diff.new_value)
+        };
+
+        // Genesis balance of the zero address is 1 wei
+        assert_eq!(
+            new_value(existing_account, BALANCE_LEAF_KEY),
+            Some(TrieValue::from(U256::from(1) + U256::from(7) * GWEI_TO_WEI))
+        );
+        assert_eq!(new_value(existing_account, NONCE_LEAF_KEY), None);
+        assert_eq!(
+            new_value(new_account, BALANCE_LEAF_KEY),
+            Some(TrieValue::from(U256::from(3) * GWEI_TO_WEI))
+        );
+        assert_eq!(
+            new_value(new_account, CODE_KECCAK_LEAF_KEY),
+            Some(TrieValue::from(keccak256([])))
+        );
+        Ok(())
+    }
+
     #[test]
     fn process_block_1000() -> Result<()> {
         let mut evm = VerkleEvm::new(read_genesis_for_test()?)?;
@@ -0,0 +1,200 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use alloy_primitives::B256;
+use anyhow::anyhow;
+use ethportal_api::{
+    types::{content_key::verkle::LeafFragmentKey, verkle::ContentInfo},
+    OverlayContentKey, VerkleContentKey, VerkleContentValue,
+};
+use portal_verkle_primitives::{
+    constants::PORTAL_NETWORK_NODE_WIDTH,
+    portal::PortalVerkleNodeWithProof,
+    verkle::{
+        nodes::{
+            portal_branch_node_builder::PortalBranchNodeBuilder,
+            portal_leaf_node_builder::PortalLeafNodeBuilder,
+        },
+        StateWrites, VerkleTrie,
+    },
+};
+
+use crate::{content_value::strip_proof, portal_transport::PortalTransport};
+
+/// The [PortalTransport] that stores gossiped content in memory, the way a Portal Network node
+/// would (without proofs), and answers content lookups from it.
+///
+/// Looking up unknown content returns error. Clones share the same content.
+#[derive(Clone, Default)]
+pub struct InMemoryPortalNode {
+    content: Arc<Mutex<HashMap<B256, VerkleContentValue>>>,
+}
+
+impl InMemoryPortalNode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored content items.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn contains(&self, key: &VerkleContentKey) -> bool {
+        self.lock().contains_key(&B256::from(key.content_id()))
+    }
+
+    /// Returns the stored content value, without sending a lookup.
+    pub fn get(&self, key: &VerkleContentKey) -> Option<VerkleContentValue> {
+        self.lock().get(&B256::from(key.content_id())).cloned()
+    }
+
+    /// Gossips all nodes of the state trie that is created from the state writes.
+    ///
+    /// Returns the state root and the keys of the gossiped content.
+    pub async fn gossip_trie(
+        &self,
+        state_writes: &StateWrites,
+    ) -> anyhow::Result<(B256, Vec<VerkleContentKey>)> {
+        let mut trie = VerkleTrie::new();
+        trie.update(state_writes);
+
+        let mut content = vec![];
+        let mut branch_paths = HashSet::new();
+        for stem_state_write in state_writes.iter() {
+            let stem = &stem_state_write.stem;
+            let path_to_leaf = trie.traverse_to_leaf(stem)?;
+
+            for depth in 0..path_to_leaf.trie_path.len() {
+                if !branch_paths.insert(stem[..depth].to_vec()) {
+                    continue;
+                }
+                let (branch, _) = path_to_leaf.trie_path[depth];
+                let builder =
+                    PortalBranchNodeBuilder::new(branch, &path_to_leaf.trie_path[..depth])?;
+                content.push((
+                    VerkleContentKey::Bundle(builder.bundle_commitment().clone()),
+                    VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::BranchBundle(
+                        builder.bundle_node_with_proof(B256::ZERO),
+                    )),
+                ));
+                for fragment_index in 0..PORTAL_NETWORK_NODE_WIDTH as u8 {
+                    let commitment = builder.fragment_commitment(fragment_index);
+                    if commitment.is_zero() {
+                        continue;
+                    }
+                    content.push((
+                        VerkleContentKey::BranchFragment(commitment.clone()),
+                        VerkleContentValue::NodeWithProof(
+                            PortalVerkleNodeWithProof::BranchFragment(
+                                builder.fragment_node_with_proof(fragment_index, B256::ZERO),
+                            ),
+                        ),
+                    ));
+                }
+            }
+
+            let builder = PortalLeafNodeBuilder::new(&path_to_leaf);
+            content.push((
+                VerkleContentKey::Bundle(builder.bundle_commitment().clone()),
+                VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::LeafBundle(
+                    builder.bundle_node_with_proof(B256::ZERO),
+                )),
+            ));
+            for fragment_index in 0..PORTAL_NETWORK_NODE_WIDTH as u8 {
+                let commitment = builder.fragment_commitment(fragment_index);
+                if commitment.is_zero() {
+                    continue;
+                }
+                content.push((
+                    VerkleContentKey::LeafFragment(LeafFragmentKey {
+                        stem: *stem,
+                        commitment: commitment.clone(),
+                    }),
+                    VerkleContentValue::NodeWithProof(PortalVerkleNodeWithProof::LeafFragment(
+                        builder.fragment_node_with_proof(fragment_index, B256::ZERO),
+                    )),
+                ));
+            }
+        }
+
+        let mut keys = vec![];
+        for (key, value) in content {
+            self.gossip(key.clone(), value).await?;
+            keys.push(key);
+        }
+        Ok((trie.root(), keys))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<B256, VerkleContentValue>> {
+        self.content
+            .lock()
+            .expect("content lock shouldn't be poisoned")
+    }
+}
+
+impl PortalTransport for InMemoryPortalNode {
+    async fn gossip(
+        &self,
+        key: VerkleContentKey,
+        value: VerkleContentValue,
+    ) -> anyhow::Result<u32> {
+        self.lock()
+            .insert(B256::from(key.content_id()), strip_proof(value));
+        Ok(1)
+    }
+
+    async fn recursive_find_content(&self, key: VerkleContentKey) -> anyhow::Result<ContentInfo> {
+        let value = self
+            .get(&key)
+            .ok_or_else(|| anyhow!("Content not found: {}", key.to_hex()))?;
+        Ok(ContentInfo::Content {
+            content: Box::new(value),
+            utp_transfer: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use portal_verkle_primitives::Point;
+
+    use super::*;
+    use crate::portal_client::{
+        PortalClientError, ResilientPortalClient, ResilientPortalClientConfig,
+    };
+
+    #[tokio::test]
+    async fn unknown_content_is_retried() {
+        let node = InMemoryPortalNode::new();
+        let client = ResilientPortalClient::with_transport(
+            node.clone(),
+            ResilientPortalClientConfig {
+                max_retries: 2,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            },
+        );
+
+        let key = VerkleContentKey::Bundle(Point::from(&B256::ZERO));
+        let err = client
+            .recursive_find_content(key.clone())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<PortalClientError>(),
+            Some(PortalClientError::Rpc { retries: 2, .. })
+        ));
+        assert!(!node.contains(&key));
+        assert!(node.is_empty());
+    }
+}
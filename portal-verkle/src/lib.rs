@@ -1,5 +1,12 @@
 pub mod beacon_block_fetcher;
+pub mod content_validation;
+pub mod content_value;
 pub mod evm;
+#[cfg(any(test, feature = "testing"))]
+pub mod in_memory_portal_node;
+pub mod portal_client;
+pub mod portal_transport;
 pub mod state_trie_fetcher;
+pub mod trie_snapshot;
 pub mod types;
 pub mod utils;
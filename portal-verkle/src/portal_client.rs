@@ -0,0 +1,364 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::{builder::RangedU64ValueParser, Args};
+use ethportal_api::{types::verkle::ContentInfo, VerkleContentKey, VerkleContentValue};
+use jsonrpsee::{
+    core::Error as RpcError,
+    http_client::{HttpClient, HttpClientBuilder},
+};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+use crate::portal_transport::PortalTransport;
+
+#[derive(Debug, Clone)]
+pub struct ResilientPortalClientConfig {
+    /// The timeout of a single request.
+    pub request_timeout: Duration,
+    /// The number of times a failed request is retried.
+    pub max_retries: u32,
+    /// The delay before the first retry. It doubles with every following retry.
+    pub initial_backoff: Duration,
+    /// The number of consecutive failures after which the circuit breaker trips.
+    pub circuit_breaker_threshold: u32,
+    /// How long the circuit breaker stays open before allowing requests again.
+    pub circuit_breaker_cooldown: Duration,
+    /// The maximum number of requests that can be in flight at the same time.
+    ///
+    /// This also bounds the number of connections that the underlying http client opens, as idle
+    /// connections are kept alive and reused by the following requests.
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for ResilientPortalClientConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(60),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            circuit_breaker_threshold: 10,
+            circuit_breaker_cooldown: Duration::from_secs(30),
+            max_concurrent_requests: 64,
+        }
+    }
+}
+
+/// The command line arguments that configure [ResilientPortalClient].
+#[derive(Args, Debug, Clone)]
+pub struct PortalClientArgs {
+    /// The timeout of a single Portal Network request, in seconds.
+    #[arg(
+        long,
+        default_value_t = 60,
+        value_parser = RangedU64ValueParser::<u64>::new().range(1..),
+    )]
+    pub request_timeout_secs: u64,
+    /// The maximum number of concurrent Portal Network requests (and open connections).
+    #[arg(
+        long,
+        default_value_t = 64,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+    )]
+    pub max_concurrent_requests: usize,
+}
+
+impl PortalClientArgs {
+    pub fn config(&self) -> ResilientPortalClientConfig {
+        ResilientPortalClientConfig {
+            request_timeout: Duration::from_secs(self.request_timeout_secs),
+            max_concurrent_requests: self.max_concurrent_requests,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PortalClientError {
+    #[error("Circuit breaker is open after {consecutive_failures} consecutive failures")]
+    CircuitOpen { consecutive_failures: u32 },
+    #[error("Request failed after {retries} retries: {source}")]
+    Rpc {
+        retries: u32,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("Request timed out after {timeout:?}, {retries} retries")]
+    Timeout { timeout: Duration, retries: u32 },
+}
+
+/// Portal Network client that retries failed requests, limits the number of concurrent requests
+/// and stops sending requests (for some time) after too many consecutive failures.
+pub struct ResilientPortalClient<T = HttpClient> {
+    transport: T,
+    config: ResilientPortalClientConfig,
+    semaphore: Semaphore,
+    consecutive_failures: AtomicU32,
+    circuit_opened_at: Mutex<Option<Instant>>,
+}
+
+impl ResilientPortalClient {
+    pub fn new(portal_rpc_url: &str, config: ResilientPortalClientConfig) -> anyhow::Result<Self> {
+        let client = HttpClientBuilder::new()
+            .request_timeout(config.request_timeout)
+            .build(portal_rpc_url)?;
+        Ok(Self::with_transport(client, config))
+    }
+}
+
+impl<T> ResilientPortalClient<T> {
+    pub fn with_transport(transport: T, config: ResilientPortalClientConfig) -> Self {
+        Self {
+            transport,
+            semaphore: Semaphore::new(config.max_concurrent_requests),
+            config,
+            consecutive_failures: AtomicU32::new(0),
+            circuit_opened_at: Mutex::new(None),
+        }
+    }
+
+    async fn call<R, F, Fut>(&self, request: F) -> Result<R, PortalClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<R>>,
+    {
+        let mut backoff = self.config.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            self.check_circuit()?;
+
+            let result = {
+                let _permit = self
+                    .semaphore
+                    .acquire()
+                    .await
+                    .expect("semaphore shouldn't be closed");
+                tokio::time::timeout(self.config.request_timeout, request()).await
+            };
+
+            let err = match result {
+                Ok(Ok(value)) => {
+                    self.consecutive_failures.store(0, Ordering::Relaxed);
+                    return Ok(value);
+                }
+                Ok(Err(err)) if is_timeout(&err) => None,
+                Ok(Err(err)) => Some(err),
+                Err(_elapsed) => None,
+            };
+
+            let consecutive_failures =
+                self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if consecutive_failures >= self.config.circuit_breaker_threshold {
+                self.open_circuit();
+            }
+            if attempt == self.config.max_retries {
+                return Err(match err {
+                    Some(err) => PortalClientError::Rpc {
+                        retries: attempt,
+                        source: err,
+                    },
+                    None => PortalClientError::Timeout {
+                        timeout: self.config.request_timeout,
+                        retries: attempt,
+                    },
+                });
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// Returns error if circuit breaker is open.
+    ///
+    /// Once the cooldown passes, the circuit is half-open: requests are allowed, and the first
+    /// failure trips it again.
+    fn check_circuit(&self) -> Result<(), PortalClientError> {
+        let mut circuit_opened_at = self
+            .circuit_opened_at
+            .lock()
+            .expect("circuit breaker lock shouldn't be poisoned");
+        match *circuit_opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.config.circuit_breaker_cooldown => {
+                Err(PortalClientError::CircuitOpen {
+                    consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+                })
+            }
+            Some(_) => {
+                *circuit_opened_at = None;
+                self.consecutive_failures.store(
+                    self.config.circuit_breaker_threshold.saturating_sub(1),
+                    Ordering::Relaxed,
+                );
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn open_circuit(&self) {
+        *self
+            .circuit_opened_at
+            .lock()
+            .expect("circuit breaker lock shouldn't be poisoned") = Some(Instant::now());
+    }
+}
+
+/// Whether the error is caused by the transport's own request timeout.
+fn is_timeout(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<RpcError>(),
+        Some(RpcError::RequestTimeout)
+    )
+}
+
+impl<T: PortalTransport + Sync> PortalTransport for ResilientPortalClient<T> {
+    async fn gossip(
+        &self,
+        key: VerkleContentKey,
+        value: VerkleContentValue,
+    ) -> anyhow::Result<u32> {
+        Ok(self
+            .call(|| self.transport.gossip(key.clone(), value.clone()))
+            .await?)
+    }
+
+    async fn recursive_find_content(&self, key: VerkleContentKey) -> anyhow::Result<ContentInfo> {
+        Ok(self
+            .call(|| self.transport.recursive_find_content(key.clone()))
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use anyhow::anyhow;
+    use clap::Parser;
+
+    use super::*;
+
+    const LOCALHOST_PORTAL_RPC_URL: &str = "http://localhost:8545/";
+
+    fn create_client(max_retries: u32, circuit_breaker_threshold: u32) -> ResilientPortalClient {
+        let config = ResilientPortalClientConfig {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            circuit_breaker_threshold,
+            ..Default::default()
+        };
+        ResilientPortalClient::new(LOCALHOST_PORTAL_RPC_URL, config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let client = create_client(3, 10);
+        let calls = AtomicUsize::new(0);
+
+        let result = client
+            .call(|| async {
+                if calls.fetch_add(1, Ordering::Relaxed) < 2 {
+                    Err(anyhow!("flaky"))
+                } else {
+                    Ok(42)
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn fails_after_retries() {
+        let client = create_client(2, 10);
+        let calls = AtomicUsize::new(0);
+
+        let result: Result<(), _> = client
+            .call(|| async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(anyhow!("down"))
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(PortalClientError::Rpc { retries: 2, .. })
+        ));
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let config = ResilientPortalClientConfig {
+            request_timeout: Duration::from_millis(10),
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let client = ResilientPortalClient::new(LOCALHOST_PORTAL_RPC_URL, config).unwrap();
+        let calls = AtomicUsize::new(0);
+
+        let result = client
+            .call(|| async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Ok(())
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(PortalClientError::Timeout { retries: 1, .. })
+        ));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_trips() {
+        let client = create_client(0, 2);
+        let calls = AtomicUsize::new(0);
+        let failing_request = || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err::<(), _>(anyhow!("down"))
+        };
+
+        assert!(client.call(failing_request).await.is_err());
+        assert!(client.call(failing_request).await.is_err());
+        let result = client.call(failing_request).await;
+
+        assert!(matches!(result, Err(PortalClientError::CircuitOpen { .. })));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        portal_client: PortalClientArgs,
+    }
+
+    #[test]
+    fn max_concurrent_requests_is_positive() {
+        let args = TestArgs::parse_from(["test", "--max-concurrent-requests", "1"]);
+        assert_eq!(args.portal_client.config().max_concurrent_requests, 1);
+        assert!(TestArgs::try_parse_from(["test", "--max-concurrent-requests", "0"]).is_err());
+    }
+
+    #[test]
+    fn request_timeout_secs_is_positive() {
+        let args = TestArgs::parse_from(["test", "--request-timeout-secs", "1"]);
+        assert_eq!(
+            args.portal_client.config().request_timeout,
+            Duration::from_secs(1)
+        );
+        assert!(TestArgs::try_parse_from(["test", "--request-timeout-secs", "0"]).is_err());
+    }
+}
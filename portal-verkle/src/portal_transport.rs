@@ -0,0 +1,36 @@
+use std::future::Future;
+
+use ethportal_api::{
+    types::verkle::ContentInfo, VerkleContentKey, VerkleContentValue, VerkleNetworkApiClient,
+};
+use jsonrpsee::http_client::HttpClient;
+
+/// The transport used to communicate with the Portal Network.
+pub trait PortalTransport {
+    /// Gossips the content. Returns the number of peers that accepted it.
+    fn gossip(
+        &self,
+        key: VerkleContentKey,
+        value: VerkleContentValue,
+    ) -> impl Future<Output = anyhow::Result<u32>> + Send;
+
+    /// Looks up the content in the network.
+    fn recursive_find_content(
+        &self,
+        key: VerkleContentKey,
+    ) -> impl Future<Output = anyhow::Result<ContentInfo>> + Send;
+}
+
+impl PortalTransport for HttpClient {
+    async fn gossip(
+        &self,
+        key: VerkleContentKey,
+        value: VerkleContentValue,
+    ) -> anyhow::Result<u32> {
+        Ok(VerkleNetworkApiClient::gossip(self, key, value).await?)
+    }
+
+    async fn recursive_find_content(&self, key: VerkleContentKey) -> anyhow::Result<ContentInfo> {
+        Ok(VerkleNetworkApiClient::recursive_find_content(self, key).await?)
+    }
+}
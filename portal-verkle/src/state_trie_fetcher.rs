@@ -1,118 +1,582 @@
-use std::time::Duration;
+use std::{collections::HashSet, fmt};
 
 use alloy_primitives::B256;
-use anyhow::bail;
 use ethportal_api::{
     types::{content_key::verkle::LeafFragmentKey, verkle::ContentInfo},
-    ContentValue, OverlayContentKey, VerkleContentKey, VerkleContentValue, VerkleNetworkApiClient,
+    ContentValue, OverlayContentKey, VerkleContentKey, VerkleContentValue,
 };
-use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
 use portal_verkle_primitives::{
     constants::PORTAL_NETWORK_NODE_WIDTH,
-    portal::PortalVerkleNode,
+    ssz::TriePath,
     verkle::{StateWrites, StemStateWrite, VerkleTrie},
     Point,
 };
+use thiserror::Error;
 
-pub struct StateTrieFetcher {
-    portal_client: HttpClient,
+use crate::{
+    content_validation::{ContentValidationError, ValidateContent},
+    content_value::VerkleContentValueExt,
+    portal_client::{ResilientPortalClient, ResilientPortalClientConfig},
+    portal_transport::PortalTransport,
+};
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("Couldn't find content for key: {key}")]
+    NotFound { key: String },
+    #[error("Empty branch on the path prefix: {prefix:?}")]
+    EmptyBranch { prefix: TriePath },
+    #[error("Invalid content value received: {value}")]
+    InvalidContent { value: String },
+    #[error("Content verification failed: {0}")]
+    VerificationFailed(#[from] ContentValidationError),
+    #[error("Fetched state trie has wrong root. Expected {expected}, but actual {actual}")]
+    RootMismatch { expected: B256, actual: B256 },
+    #[error("Fetch budget exceeded: fetched {fetched} bytes, but budget is {budget} bytes")]
+    BudgetExceeded { fetched: u64, budget: u64 },
+    #[error("Portal Network request failed: {0}")]
+    Transport(#[source] anyhow::Error),
+}
+
+impl FetchError {
+    fn invalid_content(value: &VerkleContentValue) -> Self {
+        Self::InvalidContent {
+            value: value.to_hex(),
+        }
+    }
+}
+
+/// The error of the fetch with the budget, together with the part of the trie fetched so far.
+pub struct PartialFetchError {
+    pub error: FetchError,
+    pub partial_trie: VerkleTrie,
+}
+
+impl fmt::Debug for PartialFetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartialFetchError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for PartialFetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for PartialFetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.error)
+    }
+}
+
+pub struct StateTrieFetcher<T = ResilientPortalClient> {
+    portal_client: T,
 }
 
 impl StateTrieFetcher {
-    pub fn new(portal_rpc_url: &str) -> anyhow::Result<StateTrieFetcher> {
-        let portal_client = HttpClientBuilder::new()
-            .request_timeout(Duration::from_secs(60))
-            .build(portal_rpc_url)?;
-        Ok(Self { portal_client })
+    pub fn new(
+        portal_rpc_url: &str,
+        config: ResilientPortalClientConfig,
+    ) -> anyhow::Result<StateTrieFetcher> {
+        let portal_client = ResilientPortalClient::new(portal_rpc_url, config)?;
+        Ok(Self::with_transport(portal_client))
+    }
+}
+
+impl<T: PortalTransport> StateTrieFetcher<T> {
+    pub fn with_transport(portal_client: T) -> Self {
+        Self { portal_client }
+    }
+
+    pub async fn fetch_state_trie(&self, state_root: B256) -> Result<VerkleTrie, FetchError> {
+        let trie = self
+            .fetch_trie(VerkleContentKey::Bundle(Point::from(&state_root)))
+            .await?;
+        check_root(&trie, state_root)?;
+        Ok(trie)
+    }
+
+    /// Fetches the state trie without verifying the content against the commitments from their
+    /// content keys.
+    ///
+    /// The content is still checked to be of the type that matches its key, but the node that
+    /// serves it is trusted to provide the correct content. This should only be used with a
+    /// trusted Portal Network node (e.g. one operated by the caller).
+    ///
+    /// The root of the fetched trie is still compared to the `state_root`, so invalid content is
+    /// detected, but only after the whole trie is fetched.
+    pub async fn fetch_state_trie_unchecked(
+        &self,
+        state_root: B256,
+    ) -> Result<VerkleTrie, FetchError> {
+        let mut trie = VerkleTrie::new();
+        self.fetch_into_trie(
+            VerkleContentKey::Bundle(Point::from(&state_root)),
+            &mut trie,
+            /* budget= */ None,
+            /* verify= */ false,
+        )
+        .await?;
+        check_root(&trie, state_root)?;
+        Ok(trie)
+    }
+
+    /// Fetches the state trie, stopping once more than `max_bytes` of content is fetched.
+    ///
+    /// The size of the content is the size of its SSZ encoding. In case of any error (including
+    /// [FetchError::BudgetExceeded]), the part of the trie that was fetched is returned with it.
+    pub async fn fetch_state_trie_with_budget(
+        &self,
+        state_root: B256,
+        max_bytes: u64,
+    ) -> Result<VerkleTrie, PartialFetchError> {
+        let mut trie = VerkleTrie::new();
+        let result = self
+            .fetch_into_trie(
+                VerkleContentKey::Bundle(Point::from(&state_root)),
+                &mut trie,
+                Some(max_bytes),
+                /* verify= */ true,
+            )
+            .await;
+        if let Err(error) = result.and_then(|()| check_root(&trie, state_root)) {
+            return Err(PartialFetchError {
+                error,
+                partial_trie: trie,
+            });
+        }
+        Ok(trie)
+    }
+
+    /// Fetches only the part of the state trie that is under the given path prefix.
+    ///
+    /// The returned trie contains only the leaves of the subtree, so its root is not expected to
+    /// match the `state_root`.
+    pub async fn fetch_subtree(
+        &self,
+        state_root: B256,
+        prefix: &TriePath,
+    ) -> Result<VerkleTrie, FetchError> {
+        let bundle_key = self.find_subtree_bundle(state_root, prefix).await?;
+        self.fetch_trie(bundle_key).await
+    }
+
+    /// Walks the trie along the path prefix and returns the key of the bundle node at its end.
+    async fn find_subtree_bundle(
+        &self,
+        state_root: B256,
+        prefix: &TriePath,
+    ) -> Result<VerkleContentKey, FetchError> {
+        let mut bundle_commitment = Point::from(&state_root);
+        for &child_index in prefix.iter() {
+            let key = VerkleContentKey::Bundle(bundle_commitment.clone());
+            let value = self.fetch_content(&key).await?;
+            value.validate_against_key(&key)?;
+            if let Some(node) = value.as_branch_bundle() {
+                let fragment_index = child_index as usize / PORTAL_NETWORK_NODE_WIDTH;
+                let Some(fragment_commitment) = node
+                    .fragments()
+                    .iter_enumerated_set_items()
+                    .find_map(|(index, commitment)| {
+                        (index == fragment_index).then_some(commitment)
+                    })
+                else {
+                    return Err(FetchError::EmptyBranch {
+                        prefix: prefix.clone(),
+                    });
+                };
+
+                let fragment_key = VerkleContentKey::BranchFragment(fragment_commitment.clone());
+                let fragment_value = self.fetch_content(&fragment_key).await?;
+                fragment_value.validate_against_key(&fragment_key)?;
+                let Some(fragment) = fragment_value.as_branch_fragment() else {
+                    return Err(FetchError::invalid_content(&fragment_value));
+                };
+
+                let fragment_child_index = child_index as usize % PORTAL_NETWORK_NODE_WIDTH;
+                let Some(child_commitment) =
+                    fragment.children().iter_enumerated_set_items().find_map(
+                        |(index, commitment)| (index == fragment_child_index).then_some(commitment),
+                    )
+                else {
+                    return Err(FetchError::EmptyBranch {
+                        prefix: prefix.clone(),
+                    });
+                };
+                bundle_commitment = child_commitment.clone();
+            } else if let Some(node) = value.as_leaf_bundle() {
+                if !node.stem().starts_with(prefix) {
+                    return Err(FetchError::EmptyBranch {
+                        prefix: prefix.clone(),
+                    });
+                }
+                return Ok(key);
+            } else {
+                return Err(FetchError::invalid_content(&value));
+            }
+        }
+        Ok(VerkleContentKey::Bundle(bundle_commitment))
     }
 
-    pub async fn fetch_state_trie(&self, state_root: B256) -> anyhow::Result<VerkleTrie> {
+    /// Fetches all nodes reachable from the given bundle node and creates trie out of them.
+    async fn fetch_trie(&self, bundle_key: VerkleContentKey) -> Result<VerkleTrie, FetchError> {
         let mut trie = VerkleTrie::new();
-        let mut stack = vec![VerkleContentKey::Bundle(Point::from(&state_root))];
+        self.fetch_into_trie(
+            bundle_key, &mut trie, /* budget= */ None, /* verify= */ true,
+        )
+        .await?;
+        Ok(trie)
+    }
+
+    /// Fetches all nodes reachable from the given bundle node and inserts them into the trie.
+    ///
+    /// Every content is fetched only once, even if it's reachable from multiple nodes (which
+    /// shouldn't happen in a valid trie).
+    ///
+    /// If `budget` is set, returns [FetchError::BudgetExceeded] once more than `budget` bytes of
+    /// content is fetched. If `verify` is not set, only the structure of the content is validated.
+    async fn fetch_into_trie(
+        &self,
+        bundle_key: VerkleContentKey,
+        trie: &mut VerkleTrie,
+        budget: Option<u64>,
+        verify: bool,
+    ) -> Result<(), FetchError> {
+        let mut visited = HashSet::from([B256::from(bundle_key.content_id())]);
+        let mut stack = vec![bundle_key];
+        let mut fetched = 0;
+        let mut push_once = |stack: &mut Vec<VerkleContentKey>, key: VerkleContentKey| {
+            if visited.insert(B256::from(key.content_id())) {
+                stack.push(key);
+            }
+        };
 
         while let Some(key) = stack.pop() {
             let value = self.fetch_content(&key).await?;
-            match &value {
-                VerkleContentValue::Node(PortalVerkleNode::BranchBundle(node)) => {
-                    let VerkleContentKey::Bundle(key_commitment) = &key else {
-                        bail!(
-                            "Invalid BranchBundle value received! key: {}, value: {}",
-                            key.to_hex(),
-                            value.to_hex()
-                        )
-                    };
-                    node.verify(key_commitment)?;
-
-                    for commitment in node.fragments().iter_set_items() {
-                        stack.push(VerkleContentKey::BranchFragment(commitment.clone()));
-                    }
+            if let Some(budget) = budget {
+                fetched += value.encode().len() as u64;
+                if fetched > budget {
+                    return Err(FetchError::BudgetExceeded { fetched, budget });
+                }
+            }
+            if verify {
+                value.validate_against_key(&key)?;
+            } else {
+                value.validate_structure_against_key(&key)?;
+            }
+            if let Some(node) = value.as_branch_bundle() {
+                for commitment in node.fragments().iter_set_items() {
+                    push_once(
+                        &mut stack,
+                        VerkleContentKey::BranchFragment(commitment.clone()),
+                    );
                 }
-                VerkleContentValue::Node(PortalVerkleNode::LeafBundle(node)) => {
-                    let VerkleContentKey::Bundle(key_commitment) = &key else {
-                        bail!(
-                            "Invalid LeafBundle value received! key: {}, value: {}",
-                            key.to_hex(),
-                            value.to_hex()
-                        )
-                    };
-                    node.verify(key_commitment)?;
-
-                    for commitment in node.fragments().iter_set_items() {
-                        stack.push(VerkleContentKey::LeafFragment(LeafFragmentKey {
+            } else if let Some(node) = value.as_leaf_bundle() {
+                for commitment in node.fragments().iter_set_items() {
+                    push_once(
+                        &mut stack,
+                        VerkleContentKey::LeafFragment(LeafFragmentKey {
                             stem: *node.stem(),
                             commitment: commitment.clone(),
-                        }));
-                    }
+                        }),
+                    );
                 }
-                VerkleContentValue::Node(PortalVerkleNode::BranchFragment(node)) => {
-                    let VerkleContentKey::BranchFragment(key_commitment) = &key else {
-                        bail!(
-                            "Invalid BranchFragment value received! key: {}, value: {}",
-                            key.to_hex(),
-                            value.to_hex()
-                        )
-                    };
-                    node.verify(key_commitment)?;
-
-                    for commitment in node.children().iter_set_items() {
-                        stack.push(VerkleContentKey::Bundle(commitment.clone()));
-                    }
+            } else if let Some(node) = value.as_branch_fragment() {
+                for commitment in node.children().iter_set_items() {
+                    push_once(&mut stack, VerkleContentKey::Bundle(commitment.clone()));
                 }
-                VerkleContentValue::Node(PortalVerkleNode::LeafFragment(node)) => {
-                    let VerkleContentKey::LeafFragment(leaf_fragment_key) = &key else {
-                        bail!(
-                            "Invalid LeafFragment value received! key: {}, value: {}",
-                            key.to_hex(),
-                            value.to_hex()
-                        )
-                    };
-                    node.verify(&leaf_fragment_key.commitment)?;
-
-                    let start_index = node.fragment_index() as usize * PORTAL_NETWORK_NODE_WIDTH;
-                    let stem_state_write = StemStateWrite {
-                        stem: leaf_fragment_key.stem,
-                        writes: node
-                            .children()
-                            .iter_enumerated_set_items()
-                            .map(|(child_index, value)| ((start_index + child_index) as u8, *value))
-                            .collect(),
-                    };
-                    trie.update(&StateWrites::new(vec![stem_state_write]));
-                }
-                _ => bail!("Invalid content value received: {}", value.to_hex()),
+            } else if let (VerkleContentKey::LeafFragment(leaf_fragment_key), Some(node)) =
+                (&key, value.as_leaf_fragment())
+            {
+                let start_index = node.fragment_index() as usize * PORTAL_NETWORK_NODE_WIDTH;
+                let stem_state_write = StemStateWrite {
+                    stem: leaf_fragment_key.stem,
+                    writes: node
+                        .children()
+                        .iter_enumerated_set_items()
+                        .map(|(child_index, value)| ((start_index + child_index) as u8, *value))
+                        .collect(),
+                };
+                trie.update(&StateWrites::new(vec![stem_state_write]));
+            } else {
+                return Err(FetchError::invalid_content(&value));
             }
         }
-        Ok(trie)
+        Ok(())
     }
 
-    async fn fetch_content(&self, key: &VerkleContentKey) -> anyhow::Result<VerkleContentValue> {
+    pub async fn fetch_content(
+        &self,
+        key: &VerkleContentKey,
+    ) -> Result<VerkleContentValue, FetchError> {
         let content_info = self
             .portal_client
             .recursive_find_content(key.clone())
-            .await?;
+            .await
+            .map_err(FetchError::Transport)?;
         let ContentInfo::Content { content, .. } = content_info else {
-            bail!("Couldn't find content for key: {}", key.to_hex())
+            return Err(FetchError::NotFound { key: key.to_hex() });
         };
         Ok(*content)
     }
 }
+
+/// Checks that the root of the trie matches the `state_root`.
+fn check_root(trie: &VerkleTrie, state_root: B256) -> Result<(), FetchError> {
+    if trie.root() != state_root {
+        return Err(FetchError::RootMismatch {
+            expected: state_root,
+            actual: trie.root(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use portal_verkle_primitives::TrieKey;
+
+    use super::*;
+    use crate::{in_memory_portal_node::InMemoryPortalNode, utils::read_genesis_for_test};
+
+    /// Returns the Portal Network node with the genesis state trie, its state root and the keys
+    /// of all its content.
+    async fn genesis_portal_node(
+    ) -> anyhow::Result<(InMemoryPortalNode, B256, Vec<VerkleContentKey>)> {
+        let portal_node = InMemoryPortalNode::new();
+        let (state_root, keys) = portal_node
+            .gossip_trie(&read_genesis_for_test()?.into_state_writes())
+            .await?;
+        Ok((portal_node, state_root, keys))
+    }
+
+    #[test]
+    fn check_root_matches() -> anyhow::Result<()> {
+        let mut trie = VerkleTrie::new();
+        trie.update(&read_genesis_for_test()?.into_state_writes());
+        let state_root = trie.root();
+
+        check_root(&trie, state_root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn check_root_mismatch() -> anyhow::Result<()> {
+        let mut trie = VerkleTrie::new();
+        trie.update(&read_genesis_for_test()?.into_state_writes());
+        let actual_root = trie.root();
+
+        assert!(matches!(
+            check_root(&trie, B256::ZERO),
+            Err(FetchError::RootMismatch { expected, actual })
+                if expected == B256::ZERO && actual == actual_root
+        ));
+        Ok(())
+    }
+
+    /// Returns whether all writes of the stem are present in the trie.
+    fn contains_stem(trie: &VerkleTrie, stem_state_write: &StemStateWrite) -> bool {
+        stem_state_write.writes.iter().all(|(suffix, value)| {
+            trie.get(&TrieKey::from_stem_and_last_byte(
+                &stem_state_write.stem,
+                *suffix,
+            )) == Some(value)
+        })
+    }
+
+    #[tokio::test]
+    async fn fetch_subtree_of_leaf() -> anyhow::Result<()> {
+        let (portal_node, state_root, _) = genesis_portal_node().await?;
+        let state_writes = read_genesis_for_test()?.into_state_writes();
+        let stem_state_write = state_writes.iter().next().expect("to find stem");
+
+        // The walk stops at the leaf bundle, before the end of the prefix
+        let prefix = TriePath::from(stem_state_write.stem[..].to_vec());
+        let subtree = StateTrieFetcher::with_transport(portal_node)
+            .fetch_subtree(state_root, &prefix)
+            .await?;
+        for other_stem_state_write in state_writes.iter() {
+            assert_eq!(
+                contains_stem(&subtree, other_stem_state_write),
+                other_stem_state_write.stem == stem_state_write.stem
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_subtree_of_branch() -> anyhow::Result<()> {
+        let (portal_node, state_root, _) = genesis_portal_node().await?;
+        let state_writes = read_genesis_for_test()?.into_state_writes();
+        // The child of the root with multiple stems is a branch
+        let child_index = (0..=u8::MAX)
+            .find(|child_index| {
+                state_writes
+                    .iter()
+                    .filter(|stem_state_write| stem_state_write.stem[0] == *child_index)
+                    .count()
+                    > 1
+            })
+            .expect("to find branch child of the root");
+
+        let subtree = StateTrieFetcher::with_transport(portal_node)
+            .fetch_subtree(state_root, &TriePath::from(vec![child_index]))
+            .await?;
+        for stem_state_write in state_writes.iter() {
+            assert_eq!(
+                contains_stem(&subtree, stem_state_write),
+                stem_state_write.stem[0] == child_index
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_subtree_of_empty_child() -> anyhow::Result<()> {
+        let (portal_node, state_root, _) = genesis_portal_node().await?;
+        let state_writes = read_genesis_for_test()?.into_state_writes();
+        let stems = state_writes
+            .iter()
+            .map(|stem_state_write| stem_state_write.stem)
+            .collect::<Vec<_>>();
+        // The empty child of the branch that is the child of the root
+        let prefix = (0..=u8::MAX)
+            .filter(|index| stems.iter().filter(|stem| stem[0] == *index).count() > 1)
+            .flat_map(|index| (0..=u8::MAX).map(move |child_index| [index, child_index]))
+            .find(|prefix| !stems.iter().any(|stem| stem[..2] == prefix[..]))
+            .expect("to find empty child");
+
+        let prefix = TriePath::from(prefix.to_vec());
+        assert!(matches!(
+            StateTrieFetcher::with_transport(portal_node)
+                .fetch_subtree(state_root, &prefix)
+                .await,
+            Err(FetchError::EmptyBranch { prefix: empty_prefix }) if empty_prefix == prefix
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_state_trie_with_budget() -> anyhow::Result<()> {
+        let (portal_node, state_root, keys) = genesis_portal_node().await?;
+        let fetcher = StateTrieFetcher::with_transport(portal_node.clone());
+
+        let total_size = keys
+            .iter()
+            .filter_map(|key| portal_node.get(key))
+            .map(|value| value.encode().len() as u64)
+            .sum::<u64>();
+        let trie = fetcher
+            .fetch_state_trie_with_budget(state_root, total_size)
+            .await?;
+        assert_eq!(trie.root(), state_root);
+
+        let budget = total_size / 2;
+        let Err(PartialFetchError {
+            error,
+            partial_trie,
+        }) = fetcher
+            .fetch_state_trie_with_budget(state_root, budget)
+            .await
+        else {
+            panic!("Expected fetch to exceed the budget");
+        };
+        assert!(matches!(
+            error,
+            FetchError::BudgetExceeded { fetched, budget: error_budget }
+                if fetched > budget && error_budget == budget
+        ));
+        let state_writes = read_genesis_for_test()?.into_state_writes();
+        assert!(state_writes
+            .iter()
+            .any(|stem_state_write| contains_stem(&partial_trie, stem_state_write)));
+        Ok(())
+    }
+
+    /// Transport that counts the lookups of every content.
+    struct CountingTransport {
+        portal_node: InMemoryPortalNode,
+        lookups: Mutex<HashMap<B256, usize>>,
+    }
+
+    impl PortalTransport for CountingTransport {
+        async fn gossip(
+            &self,
+            key: VerkleContentKey,
+            value: VerkleContentValue,
+        ) -> anyhow::Result<u32> {
+            self.portal_node.gossip(key, value).await
+        }
+
+        async fn recursive_find_content(
+            &self,
+            key: VerkleContentKey,
+        ) -> anyhow::Result<ContentInfo> {
+            *self
+                .lookups
+                .lock()
+                .expect("lookups lock shouldn't be poisoned")
+                .entry(B256::from(key.content_id()))
+                .or_default() += 1;
+            self.portal_node.recursive_find_content(key).await
+        }
+    }
+
+    #[tokio::test]
+    async fn content_is_fetched_once() -> anyhow::Result<()> {
+        let (portal_node, state_root, keys) = genesis_portal_node().await?;
+        let fetcher = StateTrieFetcher::with_transport(CountingTransport {
+            portal_node,
+            lookups: Mutex::new(HashMap::new()),
+        });
+        fetcher.fetch_state_trie(state_root).await?;
+
+        let lookups = fetcher
+            .portal_client
+            .lookups
+            .lock()
+            .expect("lookups lock shouldn't be poisoned");
+        assert_eq!(lookups.len(), keys.len());
+        for key in keys {
+            assert_eq!(lookups.get(&B256::from(key.content_id())), Some(&1));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tampered_leaf_fragment() -> anyhow::Result<()> {
+        let (portal_node, state_root, keys) = genesis_portal_node().await?;
+
+        // Replace the value of one leaf fragment with the value of a different one
+        let mut leaf_fragment_keys = keys
+            .into_iter()
+            .filter(|key| matches!(key, VerkleContentKey::LeafFragment(_)));
+        let tampered_key = leaf_fragment_keys.next().expect("to find leaf fragment");
+        let original_value = portal_node
+            .get(&tampered_key)
+            .expect("to find leaf fragment value");
+        let tampered_value = leaf_fragment_keys
+            .filter_map(|key| portal_node.get(&key))
+            .find(|value| value.encode() != original_value.encode())
+            .expect("to find different leaf fragment value");
+        portal_node.gossip(tampered_key, tampered_value).await?;
+
+        let fetcher = StateTrieFetcher::with_transport(portal_node);
+        assert!(matches!(
+            fetcher.fetch_state_trie_unchecked(state_root).await,
+            Err(FetchError::RootMismatch { expected, .. }) if expected == state_root
+        ));
+        assert!(matches!(
+            fetcher.fetch_state_trie(state_root).await,
+            Err(FetchError::VerificationFailed(
+                ContentValidationError::VerificationFailed(_)
+            ))
+        ));
+        Ok(())
+    }
+}
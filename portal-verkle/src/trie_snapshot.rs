@@ -0,0 +1,189 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use alloy_primitives::B256;
+use anyhow::bail;
+use portal_verkle_primitives::{
+    verkle::{StateWrites, StemStateWrite, VerkleTrie},
+    Stem, TrieValue,
+};
+use serde::{Deserialize, Serialize};
+
+/// The version of the snapshot file format. It should be increased with every incompatible
+/// change of the format.
+pub const TRIE_SNAPSHOT_VERSION: u32 = 2;
+
+/// The values of a single stem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StemSnapshot {
+    pub stem: Stem,
+    pub values: BTreeMap<u8, TrieValue>,
+}
+
+/// All values of the state trie at some slot.
+///
+/// It's stored as json, with the format version, slot, block number, block hash and state root as
+/// a header, followed by the values of all stems.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrieSnapshot {
+    pub version: u32,
+    pub slot: u64,
+    pub block_number: u64,
+    /// The hash of the block, if known. The next block has to be its child.
+    pub block_hash: Option<B256>,
+    pub state_root: B256,
+    pub stems: Vec<StemSnapshot>,
+}
+
+/// Tracks the values of the state trie, so they can be saved as [TrieSnapshot].
+#[derive(Debug, Default)]
+pub struct TrieSnapshotBuilder {
+    stems: BTreeMap<Stem, BTreeMap<u8, TrieValue>>,
+}
+
+impl TrieSnapshotBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, state_writes: &StateWrites) {
+        for stem_state_write in state_writes.iter() {
+            self.stems
+                .entry(stem_state_write.stem)
+                .or_default()
+                .extend(stem_state_write.writes.iter().map(|(&k, &v)| (k, v)));
+        }
+    }
+
+    pub fn build(
+        &self,
+        slot: u64,
+        block_number: u64,
+        block_hash: Option<B256>,
+        state_root: B256,
+    ) -> TrieSnapshot {
+        TrieSnapshot {
+            version: TRIE_SNAPSHOT_VERSION,
+            slot,
+            block_number,
+            block_hash,
+            state_root,
+            stems: self
+                .stems
+                .iter()
+                .map(|(stem, values)| StemSnapshot {
+                    stem: *stem,
+                    values: values.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<TrieSnapshot> for TrieSnapshotBuilder {
+    fn from(snapshot: TrieSnapshot) -> Self {
+        Self {
+            stems: snapshot
+                .stems
+                .into_iter()
+                .map(|stem_snapshot| (stem_snapshot.stem, stem_snapshot.values))
+                .collect(),
+        }
+    }
+}
+
+impl TrieSnapshot {
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads the snapshot from the file.
+    ///
+    /// Returns error if the format version of the file is not supported.
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let snapshot: Self = serde_json::from_reader(reader)?;
+        if snapshot.version != TRIE_SNAPSHOT_VERSION {
+            bail!(
+                "Unsupported trie snapshot version {}, expected {TRIE_SNAPSHOT_VERSION}",
+                snapshot.version
+            )
+        }
+        Ok(snapshot)
+    }
+
+    pub fn state_writes(&self) -> StateWrites {
+        StateWrites::new(
+            self.stems
+                .iter()
+                .map(|stem_snapshot| StemStateWrite {
+                    stem: stem_snapshot.stem,
+                    writes: stem_snapshot.values.clone().into_iter().collect(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Creates the state trie from the snapshot.
+    ///
+    /// Returns error if its root doesn't match the state root of the snapshot.
+    pub fn to_state_trie(&self) -> anyhow::Result<VerkleTrie> {
+        let mut state_trie = VerkleTrie::new();
+        state_trie.update(&self.state_writes());
+        if state_trie.root() != self.state_root {
+            bail!(
+                "Wrong state root of the trie snapshot. Expected {}, but actual {}",
+                self.state_root,
+                state_trie.root()
+            )
+        }
+        Ok(state_trie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use portal_verkle_primitives::verkle::genesis_config::GenesisConfig;
+
+    use super::*;
+    use crate::utils::{read_genesis_for_test, test_temp_file, GENESIS_STATE_ROOT};
+
+    #[test]
+    fn genesis_round_trip() -> Result<()> {
+        let mut builder = TrieSnapshotBuilder::new();
+        builder.update(&read_genesis_for_test()?.into_state_writes());
+        let snapshot = builder.build(
+            0,
+            0,
+            Some(GenesisConfig::DEVNET6_BLOCK_HASH),
+            GENESIS_STATE_ROOT,
+        );
+
+        let path = test_temp_file("genesis_round_trip.json");
+        snapshot.write_to_file(&path)?;
+        let read_snapshot = TrieSnapshot::read_from_file(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(read_snapshot, snapshot);
+        assert_eq!(read_snapshot.to_state_trie()?.root(), GENESIS_STATE_ROOT);
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_state_root() -> Result<()> {
+        let mut builder = TrieSnapshotBuilder::new();
+        builder.update(&read_genesis_for_test()?.into_state_writes());
+        let snapshot = builder.build(0, 0, None, B256::ZERO);
+
+        assert!(snapshot.to_state_trie().is_err());
+        Ok(())
+    }
+}
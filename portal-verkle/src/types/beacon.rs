@@ -1,4 +1,4 @@
-use alloy_primitives::{Bytes, B256, U64};
+use alloy_primitives::{Address, Bytes, B256, U64};
 use serde::{Deserialize, Serialize};
 
 use crate::types::witness::ExecutionWitness;
@@ -28,5 +28,15 @@ pub struct ExecutionPayload {
     pub state_root: B256,
     pub timestamp: U64,
     pub transactions: Vec<Bytes>,
+    pub withdrawals: Vec<Withdrawal>,
     pub execution_witness: ExecutionWitness,
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub index: U64,
+    pub validator_index: U64,
+    pub address: Address,
+    /// The withdrawn amount, in Gwei.
+    pub amount: U64,
+}
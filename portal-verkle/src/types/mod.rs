@@ -1,3 +1,4 @@
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 
 use self::beacon::SignedBeaconBlock;
@@ -5,13 +6,41 @@ use self::beacon::SignedBeaconBlock;
 pub mod beacon;
 pub mod witness;
 
+/// The response of the beacon node's RPC.
+///
+/// Most beacon clients wrap the block into `data` object, but some return it directly.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum JsonResponseMessage {
     Success(SuccessMessage),
     Error(ErrorMessage),
+    BareBlock(SignedBeaconBlock),
 }
 
+impl JsonResponseMessage {
+    /// Parses the response, with the beginning of the raw json in the error message if it's not
+    /// recognized.
+    pub fn parse(value: &serde_json::Value) -> anyhow::Result<Self> {
+        Self::deserialize(value).map_err(|err| {
+            let raw = value.to_string();
+            // Cut at the char boundary, as the raw json might contain multi-byte chars
+            let snippet_len = raw
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain([raw.len()])
+                .take_while(|index| *index <= MAX_UNRECOGNIZED_SNIPPET_LEN)
+                .last()
+                .unwrap_or_default();
+            let snippet = &raw[..snippet_len];
+            anyhow!("Unrecognized beacon block response ({err}): {snippet}")
+        })
+    }
+}
+
+/// The maximum length of the raw json that is included in the error of
+/// [JsonResponseMessage::parse].
+const MAX_UNRECOGNIZED_SNIPPET_LEN: usize = 200;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SuccessMessage {
     pub data: SignedBeaconBlock,
@@ -60,6 +89,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_bare_block_15() -> anyhow::Result<()> {
+        let reader = BufReader::new(File::open("testdata/beacon.block.15.bare.test.json")?);
+        let response: JsonResponseMessage = serde_json::from_reader(reader)?;
+        let JsonResponseMessage::BareBlock(bare_block) = response else {
+            panic!("Expected bare block, but got: {response:?}")
+        };
+
+        let reader = BufReader::new(File::open("testdata/beacon.block.15.test.json")?);
+        let JsonResponseMessage::Success(success_message) = serde_json::from_reader(reader)? else {
+            panic!("Expected success message")
+        };
+        assert_eq!(bare_block, success_message.data);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_unrecognized() {
+        let response = serde_json::json!({ "block": { "slot": "15" } });
+        let err = JsonResponseMessage::parse(&response).unwrap_err();
+        assert!(err.to_string().contains(r#"{"block":{"slot":"15"}}"#));
+    }
+
+    #[test]
+    fn parse_unrecognized_multi_byte_chars() {
+        // The snippet limit falls in the middle of a multi-byte char
+        let response = serde_json::json!({ "blocks": "é".repeat(MAX_UNRECOGNIZED_SNIPPET_LEN) });
+        let err = JsonResponseMessage::parse(&response).unwrap_err();
+        let snippet = err.to_string().split_once("): ").unwrap().1.to_string();
+        assert!(snippet.len() <= MAX_UNRECOGNIZED_SNIPPET_LEN);
+        assert!(snippet.starts_with(r#"{"blocks":"éé"#));
+    }
+
     #[test]
     fn parse_all_beacon_slots() -> anyhow::Result<()> {
         let beacon_dir = test_path(TESTNET_DATA_PATH).join("beacon");
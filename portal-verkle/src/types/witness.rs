@@ -1,9 +1,34 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
 
 use alloy_primitives::{Bytes, U8};
+use itertools::Itertools;
 use portal_verkle_primitives::{proof::IpaProof, verkle::StemStateWrite, Point, Stem, TrieValue};
 use serde::{Deserialize, Serialize};
 use serde_nested_with::serde_nested;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum WitnessError {
+    #[error("No writes for stem {stem}")]
+    NoWrites { stem: Stem },
+    #[error("Multiple diffs for suffix {suffix} of stem {stem}")]
+    DuplicateSuffix { stem: Stem, suffix: u8 },
+    #[error("Invalid extension status: {0:#04x}")]
+    InvalidExtStatus(u8),
+    #[error("Multiple diffs for stem {stem}")]
+    DuplicateStem { stem: Stem },
+    #[error("Stem {stem} is not sorted, as it comes after stem {previous}")]
+    UnsortedStems { previous: Stem, stem: Stem },
+    #[error("Suffix {suffix} of stem {stem} is not sorted, as it comes after suffix {previous}")]
+    UnsortedSuffixes {
+        stem: Stem,
+        previous: u8,
+        suffix: u8,
+    },
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -32,7 +57,7 @@ pub struct VerkleProof {
     #[serde(alias = "otherStems")]
     pub other_stems: Vec<Stem>,
     #[serde(alias = "depthExtensionPresent")]
-    pub depth_extension_present: Bytes,
+    pub depth_extension_present: DepthExtensionPresent,
     #[serde(alias = "commitmentsByPath")]
     pub commitments_by_path: Vec<Point>,
     pub d: Point,
@@ -40,6 +65,94 @@ pub struct VerkleProof {
     pub ipa_proof: IpaProof,
 }
 
+/// The extension status of the stem in the verkle proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtStatus {
+    /// There is no leaf node on the path of the stem.
+    AbsentEmpty = 0,
+    /// There is a leaf node with different stem on the path of the stem.
+    AbsentOther = 1,
+    /// The leaf node of the stem is present.
+    Present = 2,
+}
+
+impl TryFrom<u8> for ExtStatus {
+    type Error = WitnessError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::AbsentEmpty),
+            1 => Ok(Self::AbsentOther),
+            2 => Ok(Self::Present),
+            _ => Err(WitnessError::InvalidExtStatus(value)),
+        }
+    }
+}
+
+/// The depth and the extension status of every stem in the verkle proof.
+///
+/// Each stem is encoded as a single byte: the extension status in the lowest 3 bits and the depth
+/// in the remaining bits.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Bytes", into = "Bytes")]
+pub struct DepthExtensionPresent(Vec<u8>);
+
+impl DepthExtensionPresent {
+    const EXT_STATUS_BITS: u8 = 3;
+    const EXT_STATUS_MASK: u8 = (1 << Self::EXT_STATUS_BITS) - 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the depth and extension status of the next stem.
+    ///
+    /// Panics if depth doesn't fit into 5 bits.
+    pub fn push(&mut self, depth: u8, ext_status: ExtStatus) {
+        assert!(
+            depth < 1 << (u8::BITS as u8 - Self::EXT_STATUS_BITS),
+            "depth {depth} is too big"
+        );
+        self.0
+            .push(depth << Self::EXT_STATUS_BITS | ext_status as u8);
+    }
+
+    /// Iterates over depths and extension statuses of all stems.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, ExtStatus)> + '_ {
+        self.0.iter().map(|byte| {
+            let ext_status = ExtStatus::try_from(byte & Self::EXT_STATUS_MASK)
+                .expect("extension status should be validated");
+            (byte >> Self::EXT_STATUS_BITS, ext_status)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<Bytes> for DepthExtensionPresent {
+    type Error = WitnessError;
+
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        for byte in bytes.iter() {
+            ExtStatus::try_from(byte & Self::EXT_STATUS_MASK)
+                .map_err(|_| WitnessError::InvalidExtStatus(*byte))?;
+        }
+        Ok(Self(bytes.to_vec()))
+    }
+}
+
+impl From<DepthExtensionPresent> for Bytes {
+    fn from(depth_extension_present: DepthExtensionPresent) -> Self {
+        Bytes::from(depth_extension_present.0)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ExecutionWitness {
@@ -49,24 +162,326 @@ pub struct ExecutionWitness {
     pub verkle_proof: VerkleProof,
 }
 
+impl ExecutionWitness {
+    /// Validates that the stems of the state diff are sorted and unique, and that the suffixes of
+    /// each stem are sorted and unique.
+    pub fn validate(&self) -> Result<(), WitnessError> {
+        for (previous, stem_state_diff) in self.state_diff.iter().tuple_windows() {
+            match previous.stem.cmp(&stem_state_diff.stem) {
+                Ordering::Less => {}
+                Ordering::Equal => {
+                    return Err(WitnessError::DuplicateStem {
+                        stem: stem_state_diff.stem,
+                    })
+                }
+                Ordering::Greater => {
+                    return Err(WitnessError::UnsortedStems {
+                        previous: previous.stem,
+                        stem: stem_state_diff.stem,
+                    })
+                }
+            }
+        }
+        for stem_state_diff in &self.state_diff {
+            let stem = stem_state_diff.stem;
+            for (previous, suffix_diff) in stem_state_diff.suffix_diffs.iter().tuple_windows() {
+                let previous = previous.suffix.byte(0);
+                let suffix = suffix_diff.suffix.byte(0);
+                match previous.cmp(&suffix) {
+                    Ordering::Less => {}
+                    Ordering::Equal => return Err(WitnessError::DuplicateSuffix { stem, suffix }),
+                    Ordering::Greater => {
+                        return Err(WitnessError::UnsortedSuffixes {
+                            stem,
+                            previous,
+                            suffix,
+                        })
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VerkleProof {
+    /// Returns the number of commitments on the paths to the proven stems (`commitments_by_path`).
+    pub fn commitment_count(&self) -> usize {
+        self.commitments_by_path.len()
+    }
+}
+
 impl StemStateDiff {
+    /// Returns the writes of this diff, or `None` if there are none or the diff is malformed.
+    ///
+    /// See [Self::try_into_stem_state_write] for details.
     pub fn into_stem_state_write(self) -> Option<StemStateWrite> {
-        let writes = self
-            .suffix_diffs
-            .into_iter()
-            .flat_map(|suffix_state_diff| {
-                suffix_state_diff
-                    .new_value
-                    .map(|value| (suffix_state_diff.suffix.byte(0), value))
-            })
-            .collect::<HashMap<_, _>>();
+        self.try_into_stem_state_write().ok()
+    }
+
+    /// Returns the writes of this diff.
+    ///
+    /// Returns error if there are no writes, or if the same suffix is present more than once.
+    pub fn try_into_stem_state_write(self) -> Result<StemStateWrite, WitnessError> {
+        let mut writes = HashMap::new();
+        let mut suffixes = HashSet::new();
+        for suffix_state_diff in self.suffix_diffs {
+            let suffix = suffix_state_diff.suffix.byte(0);
+            if !suffixes.insert(suffix) {
+                return Err(WitnessError::DuplicateSuffix {
+                    stem: self.stem,
+                    suffix,
+                });
+            }
+            if let Some(value) = suffix_state_diff.new_value {
+                writes.insert(suffix, value);
+            }
+        }
         if writes.is_empty() {
-            None
+            Err(WitnessError::NoWrites { stem: self.stem })
         } else {
-            Some(StemStateWrite {
+            Ok(StemStateWrite {
                 stem: self.stem,
                 writes,
             })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::BufReader};
+
+    use super::*;
+    use crate::{
+        types::SuccessMessage,
+        utils::{beacon_slot_path, test_path},
+    };
+
+    const STEM: &str = "0x00000000000000000000000000000000000000000000000000000000000000";
+
+    fn stem_state_diff(suffix_diffs: &str) -> StemStateDiff {
+        serde_json::from_str(&format!(
+            r#"{{ "stem": "{STEM}", "suffixDiffs": [{suffix_diffs}] }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn try_into_stem_state_write() {
+        let stem_state_diff = stem_state_diff(
+            r#"
+            {
+                "suffix": "0",
+                "currentValue": null,
+                "newValue": "0x0000000000000000000000000000000000000000000000000000000000000001"
+            },
+            {
+                "suffix": "1",
+                "currentValue": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "newValue": null
+            }"#,
+        );
+
+        let stem_state_write = stem_state_diff.try_into_stem_state_write().unwrap();
+        assert_eq!(stem_state_write.writes.len(), 1);
+        assert!(stem_state_write.writes.contains_key(&0));
+    }
+
+    #[test]
+    fn try_into_stem_state_write_no_writes() {
+        let stem_state_diff = stem_state_diff(
+            r#"
+            {
+                "suffix": "1",
+                "currentValue": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "newValue": null
+            }"#,
+        );
+
+        assert!(matches!(
+            stem_state_diff.clone().try_into_stem_state_write(),
+            Err(WitnessError::NoWrites { .. })
+        ));
+        assert!(stem_state_diff.into_stem_state_write().is_none());
+    }
+
+    #[test]
+    fn depth_extension_present() {
+        let mut depth_extension_present = DepthExtensionPresent::new();
+        depth_extension_present.push(1, ExtStatus::AbsentOther);
+        depth_extension_present.push(2, ExtStatus::Present);
+        depth_extension_present.push(0, ExtStatus::AbsentEmpty);
+
+        assert_eq!(
+            Bytes::from(depth_extension_present.clone()),
+            Bytes::from(vec![0x09, 0x12, 0x00])
+        );
+        assert_eq!(
+            depth_extension_present.iter().collect::<Vec<_>>(),
+            vec![
+                (1, ExtStatus::AbsentOther),
+                (2, ExtStatus::Present),
+                (0, ExtStatus::AbsentEmpty)
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_extension_present_from_fixture() -> anyhow::Result<()> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(1)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        let verkle_proof = response
+            .data
+            .message
+            .body
+            .execution_payload
+            .execution_witness
+            .verkle_proof;
+
+        assert_eq!(
+            verkle_proof
+                .depth_extension_present
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![(1, ExtStatus::AbsentOther)]
+        );
+        assert_eq!(
+            serde_json::to_value(&verkle_proof.depth_extension_present)?,
+            serde_json::json!("0x09")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn commitments_by_path_are_unique() -> anyhow::Result<()> {
+        for slot in 1..=50 {
+            let path = test_path(beacon_slot_path(slot));
+            if !path.exists() {
+                continue;
+            }
+            let reader = BufReader::new(File::open(path)?);
+            let response: SuccessMessage = serde_json::from_reader(reader)?;
+            let verkle_proof = response
+                .data
+                .message
+                .body
+                .execution_payload
+                .execution_witness
+                .verkle_proof;
+
+            let commitments = &verkle_proof.commitments_by_path;
+            assert_eq!(verkle_proof.commitment_count(), commitments.len());
+            for (index, commitment) in commitments.iter().enumerate() {
+                assert!(
+                    !commitments[..index].contains(commitment),
+                    "Duplicate commitment in slot {slot}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn depth_extension_present_invalid_ext_status() {
+        assert!(matches!(
+            DepthExtensionPresent::try_from(Bytes::from(vec![0x0b])),
+            Err(WitnessError::InvalidExtStatus(0x0b))
+        ));
+    }
+
+    #[test]
+    fn try_into_stem_state_write_duplicate_suffix() {
+        let stem_state_diff = stem_state_diff(
+            r#"
+            {
+                "suffix": "1",
+                "currentValue": null,
+                "newValue": "0x0000000000000000000000000000000000000000000000000000000000000001"
+            },
+            {
+                "suffix": "1",
+                "currentValue": null,
+                "newValue": "0x0000000000000000000000000000000000000000000000000000000000000002"
+            }"#,
+        );
+
+        assert!(matches!(
+            stem_state_diff.try_into_stem_state_write(),
+            Err(WitnessError::DuplicateSuffix { suffix: 1, .. })
+        ));
+    }
+
+    fn read_execution_witness(slot: u64) -> anyhow::Result<ExecutionWitness> {
+        let reader = BufReader::new(File::open(test_path(beacon_slot_path(slot)))?);
+        let response: SuccessMessage = serde_json::from_reader(reader)?;
+        Ok(response
+            .data
+            .message
+            .body
+            .execution_payload
+            .execution_witness)
+    }
+
+    #[test]
+    fn validate_execution_witness() -> anyhow::Result<()> {
+        for slot in 1..=100 {
+            if !test_path(beacon_slot_path(slot)).exists() {
+                continue;
+            }
+            read_execution_witness(slot)?.validate()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validate_execution_witness_unsorted_stems() -> anyhow::Result<()> {
+        // Slot 54 has multiple stems with multiple suffixes
+        let mut execution_witness = read_execution_witness(54)?;
+        execution_witness.state_diff.swap(0, 1);
+
+        assert!(matches!(
+            execution_witness.validate(),
+            Err(WitnessError::UnsortedStems { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn validate_execution_witness_duplicate_stem() -> anyhow::Result<()> {
+        let mut execution_witness = read_execution_witness(54)?;
+        let stem_state_diff = execution_witness.state_diff[0].clone();
+        execution_witness.state_diff.insert(0, stem_state_diff);
+
+        assert!(matches!(
+            execution_witness.validate(),
+            Err(WitnessError::DuplicateStem { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn validate_execution_witness_unsorted_suffixes() -> anyhow::Result<()> {
+        let mut execution_witness = read_execution_witness(54)?;
+        execution_witness.state_diff[0].suffix_diffs.swap(0, 1);
+
+        assert!(matches!(
+            execution_witness.validate(),
+            Err(WitnessError::UnsortedSuffixes { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn validate_execution_witness_duplicate_suffix() -> anyhow::Result<()> {
+        let mut execution_witness = read_execution_witness(54)?;
+        let suffix_diffs = &mut execution_witness.state_diff[0].suffix_diffs;
+        suffix_diffs.insert(0, suffix_diffs[0].clone());
+
+        assert!(matches!(
+            execution_witness.validate(),
+            Err(WitnessError::DuplicateSuffix { .. })
+        ));
+        Ok(())
+    }
+}
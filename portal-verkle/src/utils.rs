@@ -4,6 +4,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
+#[cfg(test)]
+use alloy_primitives::{b256, B256};
 use portal_verkle_primitives::verkle::genesis_config::GenesisConfig;
 
 pub const TESTNET_DATA_PATH: &str = "data/verkle-devnet-6/";
@@ -13,17 +15,23 @@ pub fn test_path<P: AsRef<std::path::Path>>(path: P) -> PathBuf {
     PathBuf::from("..").join(path)
 }
 
+/// Returns the path of the temporary file that is unique to the test (and the process).
+#[cfg(test)]
+pub fn test_temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("portal_verkle_{}_{name}", std::process::id()))
+}
+
 pub fn beacon_slot_path(slot: u64) -> PathBuf {
     PathBuf::from(TESTNET_DATA_PATH).join(format!("beacon/slot.{slot}.json"))
 }
 
 // Genesis
 
-fn genesis_path() -> PathBuf {
+pub fn genesis_path() -> PathBuf {
     PathBuf::from(TESTNET_DATA_PATH).join("genesis.json")
 }
 
-fn read_genesis_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<GenesisConfig> {
+pub fn read_genesis_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<GenesisConfig> {
     let reader = BufReader::new(File::open(path)?);
     Ok(serde_json::from_reader(reader)?)
 }
@@ -32,6 +40,11 @@ pub fn read_genesis() -> anyhow::Result<GenesisConfig> {
     read_genesis_from_file(genesis_path())
 }
 
+/// The state root of the test genesis.
+#[cfg(test)]
+pub const GENESIS_STATE_ROOT: B256 =
+    b256!("1fbf85345a3cbba9a6d44f991b721e55620a22397c2a93ee8d5011136ac300ee");
+
 #[cfg(test)]
 pub fn read_genesis_for_test() -> anyhow::Result<GenesisConfig> {
     read_genesis_from_file(test_path(genesis_path()))